@@ -1,16 +1,105 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 pub struct Arguments {
-    #[arg(required = false, short = '1', default_value_t = false, help = "Download all available editors")]
-    pub stage_1: bool,
-    #[arg(required = false, short = '2', default_value_t = false, help = "Build VERSIONS.md")]
-    pub stage_2: bool,
+    #[command(subcommand)]
+    pub command: Command,
+    #[command(flatten)]
+    pub selection: SelectionArguments,
     #[arg(
-        required = false,
-        short = '3',
+        long,
+        global = true,
         default_value_t = false,
-        help = "Build single-header structs and api, and diff them"
+        help = "Never touch network, versions are taken from versions.json or existing directories"
     )]
-    pub stage_3: bool,
+    pub offline: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        help = "Keep extracted files once by content in this directory and hardlink trees from it"
+    )]
+    pub store: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        help = "Directory everything is written into and read from, working directory by default"
+    )]
+    pub root: Option<PathBuf>,
+    #[arg(long, global = true, help = "Path to TOML config, libil2cpp-parser.toml is used if present")]
+    pub config: Option<PathBuf>,
+    #[arg(long, global = true, help = "Override GraphQL endpoint used to list releases")]
+    pub graphql_url: Option<String>,
+    #[arg(long, global = true, help = "Override base URL of editor installers")]
+    pub download_url: Option<String>,
+    #[arg(long, global = true, help = "Override base URL of unity china editor installers")]
+    pub china_download_url: Option<String>,
+}
+
+/// Narrows down versions of every command
+#[derive(Args)]
+pub struct SelectionArguments {
+    #[arg(
+        long = "version",
+        global = true,
+        value_name = "PATTERN",
+        help = "Exact version, inclusive range like 2021.3.0f1..2022.1 or glob like 2022.3.*, may be repeated"
+    )]
+    pub versions: Vec<String>,
+    #[arg(long = "stream", global = true, value_name = "STREAM", help = "LTS, TECH, BETA or ALPHA, may be repeated")]
+    pub streams: Vec<String>,
+    #[arg(
+        long = "metadata",
+        global = true,
+        value_name = "VERSION",
+        help = "Metadata version of extracted sources, may be repeated"
+    )]
+    pub metadata_versions: Vec<u8>,
+    #[arg(long, global = true, default_value_t = false, help = "Only newest release of every minor version")]
+    pub latest_patch: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List releases from unity and save them into versions.json
+    Fetch,
+    /// Download every available editor and extract libil2cpp out of it
+    Extract {
+        #[arg(short, long, default_value_t = 2, help = "Editors downloaded at once")]
+        jobs: usize,
+        #[arg(long, default_value_t = 2, help = "Editors decompressed at once")]
+        extract_jobs: usize,
+        #[arg(long, default_value_t = 1024, help = "Memory in MiB shared by running decompressors")]
+        memory_budget: u32,
+    },
+    /// Build version table of extracted versions
+    Table {
+        #[arg(short, long, help = "Where to write the table, VERSIONS.md in root by default")]
+        output: Option<PathBuf>,
+    },
+    /// Build single-header structs and api of extracted versions
+    Headers,
+    /// Diff single-header structs and api between consecutive versions, struct layouts are compared too
+    Diff,
+    /// Show what is known about single version
+    Lookup {
+        #[arg(value_name = "VERSION", help = "Version as unity spells it, e.g. 2022.3.10f1")]
+        version: String,
+    },
+    /// Show in which versions type or field was introduced, changed or removed
+    History {
+        #[arg(value_name = "NAME", help = "Type like Il2CppClass or its field like Il2CppClass.klass")]
+        name: String,
+    },
+    /// Check extracted trees against their manifests, broken ones are downloaded again by extract
+    Verify,
+    /// Import libil2cpp from local Unity-<version>.tar.xz or installed editor directory
+    Import {
+        #[arg(required = true, value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+    /// Print how much space the store saved
+    StoreStats,
 }
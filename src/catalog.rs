@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs};
+
+use crate::error::{Error, Result};
+use crate::layout::Layout;
+use crate::version_parser::is_version_string;
+
+/// Used for versions discovered from disk, their changeset can't be recovered without network
+pub const UNKNOWN_CHANGESET: &'static str = "unknown";
+
+/// Single release as reported by unity, persisted into [`Layout::catalog`] so later runs may go offline
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Release {
+    pub version: String,
+    pub changeset: String,
+    pub release_date: Option<String>,
+    pub stream: Option<String>,
+    #[serde(default)]
+    pub entitlements: Vec<String>,
+}
+
+/// Overwrites [`Layout::catalog`] with `releases`
+pub fn save(layout: &Layout, releases: &Vec<Release>) -> Result<()> {
+    let path = layout.catalog();
+    let content = serde_json::to_string_pretty(releases).map_err(|e| Error::parse(e).with_path(&path))?;
+    fs::write(&path, content).map_err(|e| Error::io(&path, e))
+}
+
+/// Releases saved by [`save`], fails when there are none
+pub fn load(layout: &Layout) -> Result<Vec<Release>> {
+    let path = layout.catalog();
+    let content = fs::read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+    serde_json::from_str(&content).map_err(|e| Error::parse(e).with_path(&path))
+}
+
+/// Collects `<major>/<version>` directories of primary tree left by previous runs
+pub fn scan_directories(layout: &Layout) -> Vec<Release> {
+    let mut releases = vec![];
+    let Ok(majors) = fs::read_dir(layout.versions_root()) else {
+        return releases;
+    };
+
+    for major in majors.flatten() {
+        let major_name = major.file_name().to_string_lossy().to_string();
+        if major_name.parse::<u16>().is_err() || !major.path().is_dir() {
+            continue;
+        }
+        let Ok(versions) = fs::read_dir(major.path()) else {
+            continue;
+        };
+        for version in versions.flatten() {
+            let version_name = version.file_name().to_string_lossy().to_string();
+            if !version_name.starts_with(&format!("{}.", major_name)) || !is_version_string(&version_name) || !version.path().is_dir() {
+                continue;
+            }
+            releases.push(Release {
+                version: version_name,
+                changeset: UNKNOWN_CHANGESET.into(),
+                release_date: None,
+                stream: None,
+                entitlements: vec![],
+            });
+        }
+    }
+
+    releases
+}
+
+/// [`scan_directories`] with changeset, date and stream taken from [`Layout::catalog`] for versions it lists
+pub fn scan_known_directories(layout: &Layout) -> Result<Vec<Release>> {
+    let mut releases = scan_directories(layout);
+    if !layout.catalog().exists() {
+        return Ok(releases);
+    }
+
+    let known = load(layout)?.into_iter().map(|e| (e.version.clone(), e)).collect::<HashMap<_, _>>();
+    for release in &mut releases {
+        if let Some(known) = known.get(&release.version) {
+            *release = known.clone();
+        }
+    }
+    Ok(releases)
+}
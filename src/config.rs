@@ -0,0 +1,100 @@
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Error, Result};
+use crate::layout::Layout;
+use crate::version_parser::ReleaseChannel;
+
+pub const DEFAULT_CONFIG_PATH: &'static str = "libil2cpp-parser.toml";
+
+/// Part of editor archive which lands into its own `<root>/<major>/<version>` tree
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct TreeRule {
+    pub name: String,
+    #[serde(default = "TreeRule::default_root")]
+    pub root: PathBuf,
+    /// Removed from archive paths inside of this tree, e.g. `Editor/Data/il2cpp/libil2cpp`
+    #[serde(default)]
+    pub strip_prefix: String,
+    /// Globs over archive paths, `*` doesn't cross `/` while `**` does
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl TreeRule {
+    fn default_root() -> PathBuf {
+        PathBuf::from(".")
+    }
+
+    pub fn libil2cpp() -> Self {
+        Self {
+            name: "libil2cpp".into(),
+            root: Self::default_root(),
+            strip_prefix: "Editor/Data/il2cpp/libil2cpp".into(),
+            include: vec!["Editor/Data/il2cpp/libil2cpp/**".into()],
+            exclude: vec![],
+        }
+    }
+}
+
+/// Settings read from config file, endpoints can be overridden from command line as well
+#[derive(Deserialize, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// Everything is written under it, working directory by default
+    pub root: PathBuf,
+    /// Unity GraphQL endpoint used to list releases
+    pub graphql_url: String,
+    /// Base of `<base>/<changeset>/LinuxEditorInstaller/Unity-<version>.tar.xz` installer URLs
+    pub download_url: String,
+    /// Same as `download_url` but for unity china (`c`) releases
+    pub china_download_url: String,
+    /// Majors (or any version prefix) queried in addition to built-in and discovered ones
+    pub extra_majors: Vec<String>,
+    /// Trees filled from single editor download, first one is what table, headers and diff work with
+    pub trees: Vec<TreeRule>,
+    /// Deduplicated storage which extracted files are hardlinked from, trees hold plain files if unset
+    pub store: Option<PathBuf>,
+    /// Replaces built-in `data/skip.toml` with versions extract must not download
+    pub skip_list: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("."),
+            graphql_url: "https://services.unity.com/graphql".into(),
+            download_url: "https://download.unity3d.com/download_unity".into(),
+            china_download_url: "https://download.unitychina.cn/download_unity".into(),
+            extra_majors: vec![],
+            trees: vec![TreeRule::libil2cpp()],
+            store: None,
+            skip_list: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        toml::from_str(&content).map_err(|e| Error::parse(e).with_path(path))
+    }
+
+    pub fn layout(&self) -> Layout {
+        let primary = self.trees.first().map_or(TreeRule::default_root(), |e| e.root.clone());
+        Layout::new(&self.root, primary)
+    }
+
+    /// Base URL serving installers of given release channel
+    pub fn download_url(&self, channel: ReleaseChannel) -> &str {
+        match channel {
+            ReleaseChannel::China => self.china_download_url.trim_end_matches('/'),
+            ReleaseChannel::Global | ReleaseChannel::Experimental => self.download_url.trim_end_matches('/'),
+        }
+    }
+}
@@ -1,75 +1,151 @@
-use std::{env::current_dir, fs, process::Command, sync::LazyLock};
-use unity_version::UnityVersion;
+use std::{
+    ffi::OsString,
+    fmt::{self, Display},
+    fs,
+    path::{self, Path},
+    process::Command,
+};
 
-static ARGS: LazyLock<Vec<String>> = LazyLock::new(|| ["-P", "-D", "NET_4_0", "-I"].map(|e| e.to_string()).to_vec());
+use crate::error::{Error, Result};
+use crate::layout::Layout;
+use crate::unidiff;
+use crate::version_parser::DownloadableUnity;
 
+const ARGS: [&'static str; 4] = ["-P", "-D", "NET_4_0", "-I"];
+
+#[derive(Debug)]
 pub enum PostProcessError {
     NotDownloaded,
     AlreadyPreprocessed,
     InvocationFailed,
     NonUtf8Output,
+    /// Preprocessed header has none of known first declarations
+    UnknownLayout,
+}
+
+impl Display for PostProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostProcessError::NotDownloaded => write!(f, "sources are not downloaded"),
+            PostProcessError::AlreadyPreprocessed => write!(f, "headers are already preprocessed"),
+            PostProcessError::InvocationFailed => write!(f, "failed to invoke preprocessor"),
+            PostProcessError::NonUtf8Output => write!(f, "preprocessor output is not utf-8"),
+            PostProcessError::UnknownLayout => write!(f, "preprocessed header has unknown layout"),
+        }
+    }
 }
 
-fn post_process(header: String) -> String {
+fn post_process(header: String) -> Result<String, PostProcessError> {
     let start = header
         .find("typedef struct Il2CppClass Il2CppClass;")
         .or(header.find("typedef uint32_t Il2CppMethodSlot;"))
-        .expect("Failed to find start offset");
-    header.split_at(start).1.to_string()
+        .ok_or(PostProcessError::UnknownLayout)?;
+    Ok(header.split_at(start).1.to_string())
+}
+
+/// Preprocesses `struct.h` and `api.h` of downloaded version with system `cpp`
+pub fn generate_single_header(layout: &Layout, unity_version: &DownloadableUnity) -> Result<()> {
+    let root = path::absolute(layout.version_directory(unity_version)).map_err(|e| Error::io(layout.root(), e))?;
+    preprocess(&root).map_err(|e| e.with_version(&unity_version.inner))
 }
 
-pub fn generate_single_header(unity_version: &UnityVersion) -> Result<(), PostProcessError> {
-    let mut compiler_args = ARGS.clone();
-    let root = current_dir().unwrap().join(unity_version.major.to_string()).join(unity_version.version());
+fn preprocess(root: &Path) -> Result<()> {
+    let mut compiler_args = ARGS.map(OsString::from).to_vec();
 
     if !root.exists() {
-        return Err(PostProcessError::NotDownloaded);
+        return Err(Error::preprocess(PostProcessError::NotDownloaded).with_path(root));
     }
 
     let is_struct_exists = root.join("struct.h").exists();
     let is_api_exists = root.join("api.h").exists();
     if is_api_exists && is_struct_exists {
-        return Err(PostProcessError::AlreadyPreprocessed);
+        return Err(Error::preprocess(PostProcessError::AlreadyPreprocessed).with_path(root));
     }
 
     if !is_struct_exists {
         let object_internals = root.join("il2cpp-object-internals.h");
         let global_metadata_file_internals = root.join("vm").join("GlobalMetadataFileInternals.h");
 
-        compiler_args.push(root.to_str().unwrap().to_string());
+        compiler_args.push(root.into());
 
         if global_metadata_file_internals.exists() {
-            compiler_args.push("-include".to_string());
-            compiler_args.push(global_metadata_file_internals.to_str().unwrap().to_string());
+            compiler_args.push("-include".into());
+            compiler_args.push(global_metadata_file_internals.into());
         }
 
-        compiler_args.push("-include".to_string());
-        compiler_args.push(root.join("vm").join("MemoryInformation.h").to_str().unwrap().to_string());
-        compiler_args.push("-include".to_string());
-        compiler_args.push(object_internals.to_str().unwrap().to_string());
+        compiler_args.push("-include".into());
+        compiler_args.push(root.join("vm").join("MemoryInformation.h").into());
+        compiler_args.push("-include".into());
+        compiler_args.push(object_internals.into());
 
-        let struct_runner = Command::new("cpp").args(compiler_args).output().or(Err(PostProcessError::InvocationFailed))?;
-        let struct_output = String::from_utf8(struct_runner.stdout).or(Err(PostProcessError::NonUtf8Output))?;
-        fs::write(root.join("struct.h"), post_process(struct_output)).unwrap();
+        let header = root.join("struct.h");
+        let failed = |reason| Error::preprocess(reason).with_path(&header);
+        let struct_runner = Command::new("cpp")
+            .args(compiler_args)
+            .output()
+            .map_err(|_| failed(PostProcessError::InvocationFailed))?;
+        let struct_output = String::from_utf8(struct_runner.stdout).map_err(|_| failed(PostProcessError::NonUtf8Output))?;
+        let struct_output = post_process(struct_output).map_err(failed)?;
+        fs::write(&header, struct_output).map_err(|e| Error::io(&header, e))?;
     }
 
     if !is_api_exists {
-        let mut api_compiler_args = ARGS.clone();
-        api_compiler_args.push(root.to_str().unwrap().to_string());
-        api_compiler_args.push("-include".to_string());
-        api_compiler_args.push(root.join("il2cpp-api.h").to_str().unwrap().to_string());
+        let mut api_compiler_args = ARGS.map(OsString::from).to_vec();
+        api_compiler_args.push(root.into());
+        api_compiler_args.push("-include".into());
+        api_compiler_args.push(root.join("il2cpp-api.h").into());
 
+        let header = root.join("api.h");
+        let failed = |reason| Error::preprocess(reason).with_path(&header);
         let api_runner = Command::new("cpp")
             .args(api_compiler_args)
             .output()
-            .or(Err(PostProcessError::InvocationFailed))?;
-        let api_output = String::from_utf8(api_runner.stdout).or(Err(PostProcessError::NonUtf8Output))?;
-        fs::write(root.join("api.h"), post_process(api_output)).unwrap();
+            .map_err(|_| failed(PostProcessError::InvocationFailed))?;
+        let api_output = String::from_utf8(api_runner.stdout).map_err(|_| failed(PostProcessError::NonUtf8Output))?;
+        let api_output = post_process(api_output).map_err(failed)?;
+        fs::write(&header, api_output).map_err(|e| Error::io(&header, e))?;
     }
 
     Ok(())
 }
 
-pub fn diff() {
-    assert_eq!(Command::new("./diff.sh").status().unwrap().success(), true);
+/// Diffs `<name>.h` of every consecutive pair of versions having it into `<name>.diff`
+fn diff_headers(layout: &Layout, unity_versions: &[&DownloadableUnity], name: &str) -> Result<()> {
+    let header = format!("{}.h", name);
+    let path = layout.diff(name);
+    let _ = fs::remove_file(&path);
+
+    let unity_versions = unity_versions
+        .iter()
+        .filter(|e| layout.version_directory(e).join(&header).exists())
+        .collect::<Vec<_>>();
+    if unity_versions.len() < 2 {
+        return Ok(());
+    }
+
+    let mut output = vec![];
+    let mut previous: Option<(String, Vec<u8>)> = None;
+    for unity_version in unity_versions {
+        // Labels are relative to root, same as paths in table
+        let label = layout.relative_version_directory(unity_version).join(&header).to_string_lossy().to_string();
+        let source = layout.version_directory(unity_version).join(&header);
+        let content = fs::read(&source).map_err(|e| Error::io(&source, e).with_version(&unity_version.inner))?;
+        if let Some((previous_label, previous_content)) = &previous {
+            unidiff::write(previous_content, &content, previous_label, &label, &mut output);
+        }
+        previous = Some((label, content));
+    }
+    fs::write(&path, output).map_err(|e| Error::io(&path, e))
+}
+
+/// Produces `struct.diff` and `api.diff` in layout root out of preprocessed versions, ordered by version
+///
+/// Output is the same as `diff -purwB` over them, credits to <https://github.com/nneonneo/Il2CppVersions>
+pub fn diff(layout: &Layout, unity_versions: &[DownloadableUnity]) -> Result<()> {
+    let mut unity_versions = unity_versions.iter().collect::<Vec<_>>();
+    unity_versions.sort();
+    unity_versions.dedup();
+
+    diff_headers(layout, &unity_versions, "struct")?;
+    diff_headers(layout, &unity_versions, "api")
 }
@@ -1,20 +1,39 @@
 use colour::*;
 use futures_util::StreamExt;
-use reqwest::header::{CONTENT_TYPE, USER_AGENT};
-use reqwest::Client;
+use md5::Md5;
+use reqwest::header::{CONTENT_RANGE, CONTENT_TYPE, RANGE, USER_AGENT};
+use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::io::Write;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
+use crate::catalog::Release;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::layout::Layout;
+use crate::manifest::ArchiveDigest;
 use crate::version_parser::DownloadableUnity;
-use unity_version::{UnityVersion, UnityVersionType};
 
-const GRAPHQL_URL: &'static str = "https://services.unity.com/graphql";
-const VERSIONS: [&'static str; 9] = ["5", "2017", "2018", "2019", "2020", "2021", "2022", "2023", "6000"];
-const LINUX_DOWNLOAD_PATTERN: &'static str = "<span class=\"truncate\">Linux</span>";
+/// Seeds for [`discover_majors`], newer streams are found by probing
+const KNOWN_MAJORS: [&'static str; 9] = ["5", "2017", "2018", "2019", "2020", "2021", "2022", "2023", "6000"];
+const BROWSER_USER_AGENT: &'static str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/128.0.0.0 Safari/537.36 Edg/127.0.2651.105";
+/// Releases requested per GraphQL page
+const PAGE_SIZE: u16 = 100;
+const MAX_RETRIES: u32 = 8;
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_LIMIT: Duration = Duration::from_secs(120);
 
 #[derive(Serialize)]
 struct GraphQLPayloadVariables {
     pub limit: u16,
+    pub skip: usize,
     pub version: String,
 }
 
@@ -31,6 +50,11 @@ struct GraphQLReponseNode {
     version: String,
     #[serde(rename = "unityHubDeepLink")]
     unity_hub_url: String,
+    #[serde(rename = "releaseDate")]
+    release_date: Option<String>,
+    stream: Option<String>,
+    #[serde(default)]
+    entitlements: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -40,6 +64,8 @@ struct GraphQLResponseEdge {
 
 #[derive(Deserialize)]
 struct GraphQLResponseUnityReleases {
+    #[serde(rename = "totalCount")]
+    total_count: usize,
     edges: Vec<GraphQLResponseEdge>,
 }
 
@@ -54,99 +80,630 @@ struct _GraphQLResponseDummy {
     data: GraphQLResponseDummy,
 }
 
-pub async fn fetch_versions() -> Vec<(String, String)> {
-    let mut vec = Vec::with_capacity(1500);
+async fn fetch_page(client: &Client, config: &Config, version: &str, limit: u16, skip: usize) -> Result<GraphQLResponseUnityReleases> {
+    let payload = GraphQLPayload {
+        operation_name: "GetRelease".into(),
+        query: r#"query GetRelease($limit: Int, $skip: Int, $version: String!, $stream: [UnityReleaseStream!]) {getUnityReleases(limit: $limit skip: $skip stream: $stream version: $version entitlements: [XLTS]) {totalCount edges {node {version entitlements releaseDate unityHubDeepLink  stream }}}}"#.into(),
+        variables: GraphQLPayloadVariables { limit, skip, version: version.into() }
+    };
+    let response = client
+        .post(&config.graphql_url)
+        .body(serde_json::to_string(&payload).unwrap())
+        .header(CONTENT_TYPE, "application/json")
+        .send()
+        .await
+        .map_err(|e| Error::network(&config.graphql_url, format!("Failed to query releases of {}: {}", version, e)))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::http_status(&config.graphql_url, status.as_u16()));
+    }
+    let content = response
+        .text()
+        .await
+        .map_err(|e| Error::network(&config.graphql_url, format!("Failed to read releases of {}: {}", version, e)))?;
+    parse_page(version, &content)
+}
 
-    for version in VERSIONS {
-        let payload = GraphQLPayload {
-            operation_name: "GetRelease".into(),
-            query: r#"query GetRelease($limit: Int, $skip: Int, $version: String!, $stream: [UnityReleaseStream!]) {getUnityReleases(limit: $limit skip: $skip stream: $stream version: $version entitlements: [XLTS]) {totalCount edges {node {version entitlements releaseDate unityHubDeepLink  stream }}}}"#.into(),
-            variables: GraphQLPayloadVariables { limit: 1000, version: version.into() }
-        };
-        let client = Client::new();
-        let content = client
-            .post(GRAPHQL_URL)
-            .body(serde_json::to_string(&payload).unwrap())
-            .header(CONTENT_TYPE, "application/json")
-            .send()
-            .await
-            .unwrap()
-            .text()
-            .await
-            .unwrap();
-        let data: _GraphQLResponseDummy = serde_json::from_str(content.as_str()).unwrap();
-        let total = data.data.get_unity_releases;
-        total.edges.into_iter().for_each(|edge| {
-            let node = edge.node;
-            let lifetime = format!("://{}/", node.version);
-            let mut changeset_split = node.unity_hub_url.split(lifetime.as_str());
-            vec.push((node.version, changeset_split.nth(1).unwrap().to_string()))
-        });
-    }
-
-    vec
-}
-
-pub async fn is_available_for_linux(unity_version: &UnityVersion) -> Result<bool, String> {
-    let url = match unity_version.r#type {
-        UnityVersionType::Alpha => format!("https://unity.com/en/releases/editor/alpha/{}", unity_version.version()),
-        UnityVersionType::Beta => format!("https://unity.com/en/releases/editor/beta/{}", unity_version.version()),
-        UnityVersionType::Final | UnityVersionType::Patch => format!(
-            "https://unity.com/en/releases/editor/whats-new/{}.{}.{}",
-            unity_version.major, unity_version.minor, unity_version.build
-        ),
-        _ => todo!(),
+/// Server answered, so malformed body is a parse error rather than network one
+fn parse_page(version: &str, content: &str) -> Result<GraphQLResponseUnityReleases> {
+    let data: _GraphQLResponseDummy = serde_json::from_str(content).map_err(|e| Error::parse(format!("Failed to parse releases of {}: {}", version, e)))?;
+    Ok(data.data.get_unity_releases)
+}
+
+fn parse_release(node: GraphQLReponseNode) -> Result<Release> {
+    let lifetime = format!("://{}/", node.version);
+    let Some(changeset) = node.unity_hub_url.split(lifetime.as_str()).nth(1) else {
+        return Err(Error::parse(format!("No changeset in {}", node.unity_hub_url)).with_version(&node.version));
     };
+    Ok(Release {
+        changeset: changeset.to_string(),
+        version: node.version,
+        release_date: node.release_date,
+        stream: node.stream,
+        entitlements: node.entitlements,
+    })
+}
+
+async fn has_releases(client: &Client, config: &Config, major: &str) -> Result<bool> {
+    Ok(fetch_page(client, config, major, 1, 0).await?.total_count > 0)
+}
 
+/// Known and configured majors plus whatever unity published after the newest one,
+/// both next number (`2023` -> `2024`) and next thousand (`6000` -> `7000`) are probed
+pub async fn discover_majors(client: &Client, config: &Config) -> Result<Vec<String>> {
+    let mut majors = KNOWN_MAJORS
+        .iter()
+        .map(|e| e.to_string())
+        .chain(config.extra_majors.iter().cloned())
+        .collect::<Vec<_>>();
+
+    let mut newest = majors.iter().filter_map(|e| e.parse::<u32>().ok()).max().unwrap_or(0);
+    loop {
+        let candidates = [newest + 1, (newest / 1000 + 1) * 1000];
+        let mut found = None;
+        for candidate in candidates {
+            if has_releases(client, config, &candidate.to_string()).await? {
+                found = Some(candidate);
+                break;
+            }
+        }
+        match found {
+            Some(major) => {
+                green_ln!("Discovered new major {}", major);
+                majors.push(major.to_string());
+                newest = major;
+            }
+            None => break,
+        }
+    }
+
+    majors.sort();
+    majors.dedup();
+    Ok(majors)
+}
+
+/// Every release of every major, unparsed, see [`crate::fetch_versions`]
+pub async fn fetch_versions(config: &Config) -> Result<Vec<Release>> {
+    let mut vec = Vec::with_capacity(1500);
     let client = Client::new();
-    let response = client
-        .get(url)
-        .header(
-            USER_AGENT,
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/128.0.0.0 Safari/537.36 Edg/127.0.2651.105",
-        )
+
+    for version in discover_majors(&client, config).await? {
+        let version = version.as_str();
+        let mut received = 0;
+        let mut total_count;
+
+        loop {
+            let page = fetch_page(&client, config, version, PAGE_SIZE, received).await?;
+            total_count = page.total_count;
+            if page.edges.is_empty() {
+                break;
+            }
+            received += page.edges.len();
+            for edge in page.edges {
+                vec.push(parse_release(edge.node)?);
+            }
+            if received >= total_count {
+                break;
+            }
+        }
+
+        if received != total_count {
+            return Err(Error::network(
+                &config.graphql_url,
+                format!("Got {} releases of {} while server announced {}", received, version, total_count),
+            ));
+        }
+    }
+
+    Ok(vec)
+}
+
+/// Linux editor archive of version on CDN of its channel
+pub fn installer_url(config: &Config, unity_version: &DownloadableUnity) -> String {
+    format!(
+        "{}/{}/LinuxEditorInstaller/Unity-{}.tar.xz",
+        config.download_url(unity_version.channel()),
+        unity_version.changeset,
+        unity_version.name
+    )
+}
+
+/// Remembers which changesets have linux installer, persisted into [`Layout::probe_cache`]
+pub struct ProbeCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, bool>>,
+}
+
+impl ProbeCache {
+    /// Missing or broken cache file simply means everything is probed again
+    pub fn load(layout: &Layout) -> Self {
+        let path = layout.probe_cache();
+        let entries = fs::read_to_string(&path).ok().and_then(|e| serde_json::from_str(&e).ok()).unwrap_or_default();
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Overwrites [`Layout::probe_cache`] with everything probed so far, through temporary file so crash keeps previous one
+    pub fn save(&self) -> Result<()> {
+        // Lock is held while writing so concurrent saves don't race on temporary file
+        let entries = self.entries.lock().unwrap();
+        let sorted = entries.iter().collect::<BTreeMap<_, _>>();
+        let content = serde_json::to_string_pretty(&sorted).map_err(|e| Error::parse(e).with_path(&self.path))?;
+        let temporary = self.path.with_extension("json.tmp");
+        fs::write(&temporary, content).map_err(|e| Error::io(&temporary, e))?;
+        fs::rename(&temporary, &self.path).map_err(|e| Error::io(&self.path, e))
+    }
+
+    fn get(&self, changeset: &str) -> Option<bool> {
+        self.entries.lock().unwrap().get(changeset).copied()
+    }
+
+    fn insert(&self, changeset: &str, available: bool) {
+        self.entries.lock().unwrap().insert(changeset.to_string(), available);
+    }
+}
+
+/// Asks installer URL itself instead of release page, with `HEAD` and single byte `GET` for servers refusing `HEAD`
+pub async fn is_available_for_linux(config: &Config, cache: &ProbeCache, unity_version: &DownloadableUnity) -> Result<bool> {
+    if let Some(available) = cache.get(&unity_version.changeset) {
+        return Ok(available);
+    }
+
+    let url = installer_url(config, unity_version);
+    let client = Client::new();
+    let mut status = client
+        .head(&url)
+        .header(USER_AGENT, BROWSER_USER_AGENT)
         .send()
         .await
-        .or(Err(format!("Failed to get {}", unity_version)))?;
+        .map_err(|e| Error::network(&url, format!("Failed to probe: {}", e)).with_version(&unity_version.inner))?
+        .status();
+    if status == StatusCode::METHOD_NOT_ALLOWED || status == StatusCode::FORBIDDEN {
+        status = client
+            .get(&url)
+            .header(USER_AGENT, BROWSER_USER_AGENT)
+            .header(RANGE, "bytes=0-0")
+            .send()
+            .await
+            .map_err(|e| Error::network(&url, format!("Failed to probe: {}", e)).with_version(&unity_version.inner))?
+            .status();
+    }
 
-    Ok(response.text().await.or(Err("Failed to parse text"))?.contains(LINUX_DOWNLOAD_PATTERN))
+    let available = match status {
+        e if e.is_success() => true,
+        StatusCode::NOT_FOUND | StatusCode::GONE => false,
+        // Don't cache anything what might be temporary
+        e => return Err(Error::http_status(&url, e.as_u16()).with_version(&unity_version.inner)),
+    };
+    // Saved right away so interrupted extract doesn't probe everything again
+    cache.insert(&unity_version.changeset, available);
+    if let Err(e) = cache.save() {
+        red_ln!("✘ Failed to save probe cache: {}", e);
+    }
+    Ok(available)
 }
 
-pub async fn download_using_changeset(unity_version: &DownloadableUnity) -> Result<Vec<u8>, String> {
+/// Both digests are computed in single pass: sha256 for our manifests, md5 to match unity ones
+struct ArchiveHasher {
+    sha256: Sha256,
+    md5: Md5,
+}
+
+impl ArchiveHasher {
+    fn new() -> Self {
+        Self {
+            sha256: Sha256::new(),
+            md5: Md5::new(),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+        self.md5.update(data);
+    }
+
+    fn finalize(self) -> (String, String) {
+        (format!("{:x}", self.sha256.finalize()), format!("{:x}", self.md5.finalize()))
+    }
+}
+
+/// Digest of archive which is already on disk, e.g. imported one
+pub fn hash_archive(path: &Path) -> Result<ArchiveDigest> {
+    let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let mut hasher = ArchiveHasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buffer).map_err(|e| Error::io(path, e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+
+    let (sha256, md5) = hasher.finalize();
+    Ok(ArchiveDigest {
+        url: path.display().to_string(),
+        size,
+        sha256,
+        md5,
+        published_md5: None,
+    })
+}
+
+/// Looks for `md5` of `[Unity]` section in `unity-<version>-linux.ini`,
+/// it is only trusted when section points to the very same archive we download
+async fn fetch_published_md5(client: &Client, config: &Config, unity_version: &DownloadableUnity) -> Option<String> {
     let url = format!(
-        "https://download.unity3d.com/download_unity/{}/LinuxEditorInstaller/Unity-{}.tar.xz",
+        "{}/{}/unity-{}-linux.ini",
+        config.download_url(unity_version.channel()),
         unity_version.changeset,
-        unity_version.inner.version()
+        unity_version.name
     );
-    let client = Client::new();
     let response = client
         .get(url)
-        .header(
-            USER_AGENT,
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/128.0.0.0 Safari/537.36 Edg/127.0.2651.105",
-        )
+        .header(USER_AGENT, BROWSER_USER_AGENT)
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    let content = response.text().await.ok()?;
+
+    let archive = format!("LinuxEditorInstaller/Unity-{}.tar.xz", unity_version.name);
+    let mut in_unity_section = false;
+    let mut matches_archive = false;
+    let mut md5 = None;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_unity_section = line == "[Unity]";
+            continue;
+        }
+        if !in_unity_section {
+            continue;
+        }
+        match line.split_once('=') {
+            Some(("url", url)) => matches_archive = url.trim() == archive,
+            Some(("md5", value)) => md5 = Some(value.trim().to_lowercase()),
+            _ => {}
+        }
+    }
+
+    md5.filter(|_| matches_archive)
+}
+
+#[derive(Default)]
+struct ProgressState {
+    written: u64,
+    finished: Option<Result<(), String>>,
+    abandoned: bool,
+}
+
+/// Shared between downloader appending to `.part` file and [`PartReader`] following it
+#[derive(Default)]
+pub(crate) struct Progress {
+    state: Mutex<ProgressState>,
+    condvar: Condvar,
+}
+
+impl Progress {
+    /// Returns `false` once reader is gone, so there is no point to continue
+    fn advance(&self, size: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.written += size;
+        self.condvar.notify_all();
+        !state.abandoned
+    }
+
+    fn finish(&self, result: Result<(), String>) {
+        self.state.lock().unwrap().finished = Some(result);
+        self.condvar.notify_all();
+    }
+
+    pub(crate) fn is_abandoned(&self) -> bool {
+        self.state.lock().unwrap().abandoned
+    }
+}
+
+/// Blocking reader over `.part` file which waits for downloader when it reaches written end
+pub(crate) struct PartReader {
+    file: File,
+    position: u64,
+    progress: Arc<Progress>,
+}
+
+impl PartReader {
+    /// `path` is [`Layout::part`] of version being downloaded
+    pub(crate) fn open(path: &Path, progress: Arc<Progress>) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        Ok(Self { file, position: 0, progress })
+    }
+}
+
+impl Read for PartReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.progress.state.lock().unwrap();
+        loop {
+            if self.position < state.written {
+                let available = (state.written - self.position).min(buf.len() as u64) as usize;
+                drop(state);
+                let size = self.file.read(&mut buf[..available])?;
+                self.position += size as u64;
+                return Ok(size);
+            }
+            match &state.finished {
+                Some(Ok(_)) => return Ok(0),
+                Some(Err(e)) => return Err(io::Error::other(e.clone())),
+                None => state = self.progress.condvar.wait(state).unwrap(),
+            }
+        }
+    }
+}
+
+impl Drop for PartReader {
+    fn drop(&mut self) {
+        let mut state = self.progress.state.lock().unwrap();
+        // Reader gone after download failed is not abandonment, `.part` file is still worth resuming
+        if state.finished.is_none() {
+            state.abandoned = true;
+        }
+    }
+}
+
+enum Failure {
+    /// Connection issues, worth retrying
+    Transient(Error),
+    Fatal(Error),
+}
+
+fn content_range_total(response: &Response) -> Option<u64> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    value.rsplit('/').next()?.parse().ok()
+}
+
+/// Everything what survives reconnects
+struct PartState {
+    path: PathBuf,
+    file: tokio::fs::File,
+    written: u64,
+    total: Option<u64>,
+    hasher: ArchiveHasher,
+}
+
+async fn fetch_range(client: &Client, url: &str, unity_version: &DownloadableUnity, state: &mut PartState, progress: &Progress) -> Result<(), Failure> {
+    let PartState {
+        path,
+        file,
+        written,
+        total,
+        hasher,
+    } = state;
+
+    let mut request = client.get(url).header(USER_AGENT, BROWSER_USER_AGENT);
+    if *written > 0 {
+        request = request.header(RANGE, format!("bytes={}-", written));
+    }
+    let response = request
         .send()
         .await
-        .or(Err(format!("Failed to get {}", unity_version)))?;
-    match response.error_for_status() {
-        Err(e) => Err(e.to_string()),
-        Ok(res) => {
-            let total_size = res.content_length().unwrap();
+        .map_err(|e| Failure::Transient(Error::network(url, format!("Failed to get {}: {}", unity_version, e))))?;
+
+    let status = response.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        // `.part` file already holds everything server has
+        *total = content_range_total(&response).or(*total);
+        return Ok(());
+    }
+    if status.is_server_error() {
+        return Err(Failure::Transient(Error::http_status(url, status.as_u16())));
+    }
+    if !status.is_success() {
+        return Err(Failure::Fatal(Error::http_status(url, status.as_u16())));
+    }
+
+    // Server may ignore range and send whole archive again, skip what is already on disk then
+    let mut skip = 0;
+    if status == StatusCode::PARTIAL_CONTENT {
+        *total = content_range_total(&response);
+    } else {
+        skip = *written;
+        *total = response.content_length();
+    }
+
+    let total_size = total.unwrap_or(0);
+    if *written == 0 {
+        yellow_ln!("Downloading {} with {}MB", unity_version, total_size / 1024 / 1024);
+    } else {
+        yellow_ln!("Resuming {} at {}MB of {}MB", unity_version, *written / 1024 / 1024, total_size / 1024 / 1024);
+    }
+
+    let mut stream = response.bytes_stream();
+    while let Some(item) = stream.next().await {
+        let chunk = item.map_err(|e| Failure::Transient(Error::network(url, format!("Connection lost while downloading {}: {}", unity_version, e))))?;
+        let offset = skip.min(chunk.len() as u64);
+        skip -= offset;
+        let chunk = &chunk[offset as usize..];
+        if chunk.is_empty() {
+            continue;
+        }
+
+        // Flush before announcing bytes, reader must never see them before they are on disk
+        let flushed = match file.write_all(chunk).await {
+            Ok(_) => file.flush().await,
+            Err(e) => Err(e),
+        };
+        flushed.map_err(|e| Failure::Fatal(Error::io(&path, e)))?;
+        hasher.update(chunk);
+        *written += chunk.len() as u64;
+        if !progress.advance(chunk.len() as u64) {
+            return Err(Failure::Fatal(Error::decompression("Extraction stopped before download finished")));
+        }
+    }
+
+    Ok(())
+}
+
+async fn download_part(config: &Config, unity_version: &DownloadableUnity, progress: &Progress) -> Result<ArchiveDigest> {
+    let url = installer_url(config, unity_version);
+    let path = config.layout().part(unity_version);
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(|e| Error::io(parent, e))?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(&path)
+        .await
+        .map_err(|e| Error::io(&path, e))?;
 
-            yellow_ln!("Downloading {} with {:.2}MB", unity_version, total_size / 1024 / 1024);
+    // Resumed bytes must be part of digest as well
+    let mut hasher = ArchiveHasher::new();
+    let mut written = 0;
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let size = file.read(&mut buffer).await.map_err(|e| Error::io(&path, e))?;
+        if size == 0 {
+            break;
+        }
+        hasher.update(&buffer[..size]);
+        written += size as u64;
+    }
+    drop(buffer);
+    progress.advance(written);
 
-            let mut buffer = Vec::with_capacity(total_size as usize);
-            let mut stream = res.bytes_stream();
-            let start = std::time::Instant::now();
+    let client = Client::new();
+    let published_md5 = fetch_published_md5(&client, config, unity_version).await;
+    let mut state = PartState {
+        path,
+        file,
+        written,
+        total: None,
+        hasher,
+    };
+    let mut attempt = 0;
+    let start = Instant::now();
 
-            while let Some(item) = stream.next().await {
-                let chunk = item.expect("Failed downloading chunk");
-                let _ = buffer.write(&chunk).or(Err("Failed write to memory"))?;
+    loop {
+        let before = state.written;
+        match fetch_range(&client, &url, unity_version, &mut state, progress).await {
+            Ok(_) => break,
+            Err(Failure::Fatal(e)) => return Err(e),
+            Err(Failure::Transient(e)) => {
+                // Only count attempts which didn't move download forward
+                if state.written > before {
+                    attempt = 0;
+                }
+                if attempt >= MAX_RETRIES {
+                    return Err(e);
+                }
+                let delay = (BACKOFF_BASE * 2u32.pow(attempt)).min(BACKOFF_LIMIT);
+                attempt += 1;
+                yellow_ln!("{}, retrying in {}s ({}/{})", e, delay.as_secs(), attempt, MAX_RETRIES);
+                tokio::time::sleep(delay).await;
             }
+        }
+    }
+
+    // Corrupted `.part` file is useless for resuming, drop it right away
+    let size = state.written;
+    if let Some(total) = state.total {
+        if total != size {
+            let _ = tokio::fs::remove_file(&state.path).await;
+            return Err(Error::decompression(format!("Got {} bytes while server announced {}", size, total)).with_path(&state.path));
+        }
+    }
+    let (sha256, md5) = state.hasher.finalize();
+    if let Some(published) = &published_md5 {
+        if *published != md5 {
+            let _ = tokio::fs::remove_file(&state.path).await;
+            return Err(Error::decompression(format!("Archive has md5 {} while unity published {}", md5, published)).with_path(&state.path));
+        }
+    }
+
+    green_ln!("✔ Downloaded {} in {}s", unity_version, start.elapsed().as_secs());
+    Ok(ArchiveDigest {
+        url,
+        size,
+        sha256,
+        md5,
+        published_md5,
+    })
+}
+
+/// Appends editor archive to its `.part` file, resuming with `Range` requests on connection loss
+/// Digest is checked against size announced by server and md5 published by unity once download ends, by then extractor
+/// has already written trees, so on mismatch [`crate::download_and_extract`] removes them
+pub(crate) async fn download_using_changeset(config: &Config, unity_version: &DownloadableUnity, progress: &Progress) -> Result<ArchiveDigest> {
+    let result = download_part(config, unity_version, progress)
+        .await
+        .map_err(|e| e.with_version(&unity_version.inner));
+    progress.finish(result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+    result
+}
 
-            green_ln!("✔ Downloaded {} in {}s", unity_version, start.elapsed().as_secs());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            Ok(buffer)
+    fn node(unity_hub_url: &str) -> GraphQLReponseNode {
+        GraphQLReponseNode {
+            version: "2021.3.5f1".into(),
+            unity_hub_url: unity_hub_url.into(),
+            release_date: None,
+            stream: None,
+            entitlements: vec![],
         }
     }
+
+    #[test]
+    fn invalid_page_is_parse_error() {
+        let error = parse_page("2021", r#"{"data": {"getUnityReleases": null}}"#).err().unwrap();
+        assert!(matches!(error, Error::Parse { .. }), "{:?}", error);
+        let error = parse_page("2021", "<html>").err().unwrap();
+        assert!(matches!(error, Error::Parse { .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn valid_page_is_parsed() {
+        let page = parse_page(
+            "2021",
+            r#"{"data": {"getUnityReleases": {"totalCount": 1, "edges": [{"node": {"version": "2021.3.5f1", "unityHubDeepLink": "unityhub://2021.3.5f1/40eb3a945986"}}]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(page.total_count, 1);
+        let release = parse_release(page.edges.into_iter().next().unwrap().node).unwrap();
+        assert_eq!(release.changeset, "40eb3a945986");
+    }
+
+    #[test]
+    fn probe_cache_round_trip() {
+        let root = std::env::temp_dir().join(format!("libil2cpp-parser-probes-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let layout = Layout::new(&root, ".");
+        let cache = ProbeCache::load(&layout);
+        cache.insert("40eb3a945986", true);
+        cache.insert("0123456789ab", false);
+        cache.save().unwrap();
+
+        let loaded = ProbeCache::load(&layout);
+        assert_eq!(loaded.get("40eb3a945986"), Some(true));
+        assert_eq!(loaded.get("0123456789ab"), Some(false));
+        assert_eq!(loaded.get("ffffffffffff"), None);
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn missing_changeset_is_parse_error() {
+        let error = parse_release(node("unityhub://2021.3.6f1/40eb3a945986")).err().unwrap();
+        assert!(matches!(error, Error::Parse { .. }), "{:?}", error);
+        assert_eq!(error.exit_code(), Error::parse("").exit_code());
+    }
 }
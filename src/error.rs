@@ -0,0 +1,226 @@
+use std::{
+    fmt::{self, Display},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::diff::PostProcessError;
+use crate::writer::WriteError;
+
+/// What error was about, filled as it travels up through callers knowing it
+#[derive(Debug, Default, Clone)]
+pub struct Context {
+    pub version: Option<String>,
+    pub path: Option<PathBuf>,
+}
+
+/// Everything library can fail with
+#[derive(Debug)]
+pub enum Error {
+    /// Connection failed, was cut off or server talks nonsense
+    Network {
+        context: Context,
+        url: String,
+        message: String,
+    },
+    /// Server answered with unexpected status, 404 and 410 for installer mean unity removed the changeset
+    HttpStatus {
+        context: Context,
+        url: String,
+        status: u16,
+    },
+    /// Archive is not xz/tar, is cut off, has unsafe entries or doesn't match its digest
+    Decompression {
+        context: Context,
+        message: String,
+    },
+    Io {
+        context: Context,
+        source: io::Error,
+    },
+    /// `cpp` failed or produced something unusable
+    Preprocess {
+        context: Context,
+        reason: PostProcessError,
+    },
+    /// Config, catalog, manifest or sources couldn't be understood
+    Parse {
+        context: Context,
+        message: String,
+    },
+    /// Extracted tree or store object no longer matches manifest
+    Integrity {
+        context: Context,
+        message: String,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn network(url: &str, message: impl Display) -> Self {
+        Error::Network {
+            context: Context::default(),
+            url: url.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn http_status(url: &str, status: u16) -> Self {
+        Error::HttpStatus {
+            context: Context::default(),
+            url: url.to_string(),
+            status,
+        }
+    }
+
+    pub fn decompression(message: impl Display) -> Self {
+        Error::Decompression {
+            context: Context::default(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        Error::Io {
+            context: Context {
+                version: None,
+                path: Some(path.as_ref().to_path_buf()),
+            },
+            source,
+        }
+    }
+
+    pub fn preprocess(reason: PostProcessError) -> Self {
+        Error::Preprocess {
+            context: Context::default(),
+            reason,
+        }
+    }
+
+    pub fn parse(message: impl Display) -> Self {
+        Error::Parse {
+            context: Context::default(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn integrity(message: impl Display) -> Self {
+        Error::Integrity {
+            context: Context::default(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn context(&self) -> &Context {
+        match self {
+            Error::Network { context, .. }
+            | Error::HttpStatus { context, .. }
+            | Error::Decompression { context, .. }
+            | Error::Io { context, .. }
+            | Error::Preprocess { context, .. }
+            | Error::Parse { context, .. }
+            | Error::Integrity { context, .. } => context,
+        }
+    }
+
+    fn context_mut(&mut self) -> &mut Context {
+        match self {
+            Error::Network { context, .. }
+            | Error::HttpStatus { context, .. }
+            | Error::Decompression { context, .. }
+            | Error::Io { context, .. }
+            | Error::Preprocess { context, .. }
+            | Error::Parse { context, .. }
+            | Error::Integrity { context, .. } => context,
+        }
+    }
+
+    /// Sets version unless more specific caller already did
+    pub fn with_version(mut self, version: impl Display) -> Self {
+        self.context_mut().version.get_or_insert_with(|| version.to_string());
+        self
+    }
+
+    /// Sets path unless more specific caller already did
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.context_mut().path.get_or_insert_with(|| path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Only installer and its `.ini` are per changeset, 404 from anything else is plain HTTP failure
+    pub fn is_changeset_removed(&self) -> bool {
+        matches!(self, Error::HttpStatus { status: 404 | 410, url, .. } if url.ends_with(".tar.xz") || url.ends_with(".ini"))
+    }
+
+    pub fn is_disk_full(&self) -> bool {
+        matches!(self, Error::Io { source, .. } if source.kind() == ErrorKind::StorageFull)
+    }
+
+    /// Distinct per failure kind so scripts can tell them apart, see README for the table
+    pub fn exit_code(&self) -> ExitCode {
+        let code = match self {
+            Error::Network { .. } => 10,
+            _ if self.is_changeset_removed() => 12,
+            Error::HttpStatus { .. } => 11,
+            Error::Decompression { .. } => 13,
+            _ if self.is_disk_full() => 15,
+            Error::Io { .. } => 14,
+            Error::Preprocess { .. } => 16,
+            Error::Parse { .. } => 17,
+            Error::Integrity { .. } => 18,
+        };
+        ExitCode::from(code)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network { url, message, .. } => write!(f, "{} ({})", message, url)?,
+            Error::HttpStatus { url, status, .. } => write!(f, "server responded {} for {}", status, url)?,
+            Error::Decompression { message, .. } | Error::Parse { message, .. } | Error::Integrity { message, .. } => write!(f, "{}", message)?,
+            Error::Io { source, .. } => write!(f, "{}", source)?,
+            Error::Preprocess { reason, .. } => write!(f, "{}", reason)?,
+        }
+
+        let context = self.context();
+        match (&context.version, &context.path) {
+            (Some(version), Some(path)) => write!(f, " [{}, {}]", version, path.display()),
+            (Some(version), None) => write!(f, " [{}]", version),
+            (None, Some(path)) => write!(f, " [{}]", path.display()),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<WriteError> for Error {
+    fn from(e: WriteError) -> Self {
+        match e {
+            WriteError::PermissionDenied(path) => Error::io(path, ErrorKind::PermissionDenied.into()),
+            WriteError::DiskFull(path) => Error::io(path, ErrorKind::StorageFull.into()),
+            WriteError::Io { path, source } => Error::io(path, source),
+            WriteError::NonUtf8Path(ref path) | WriteError::PathTraversal(ref path) | WriteError::LinkEscape { ref path, .. } => {
+                let path = path.clone();
+                Error::decompression(e).with_path(path)
+            }
+            WriteError::Archive(_) | WriteError::BadEntry(_) => Error::decompression(e),
+        }
+    }
+}
+
+impl From<PostProcessError> for Error {
+    fn from(reason: PostProcessError) -> Self {
+        Error::preprocess(reason)
+    }
+}
@@ -0,0 +1,114 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::{Component, PathBuf};
+
+use crate::config::TreeRule;
+use crate::error::{Error, Result};
+use crate::layout::Layout;
+use crate::version_parser::DownloadableUnity;
+
+#[derive(Clone)]
+struct Tree {
+    name: String,
+    root: PathBuf,
+    strip_prefix: String,
+    include: GlobSet,
+    exclude: GlobSet,
+    /// Literal directories include globs start with, nothing outside of them can match
+    bases: Vec<String>,
+}
+
+/// Decides which archive entries go to which output tree, one entry may land in several of them
+#[derive(Clone)]
+pub struct ExtractionFilter {
+    trees: Vec<Tree>,
+    layout: Layout,
+}
+
+fn build_set(tree: &str, patterns: &Vec<String>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| Error::parse(format!("Bad glob {} of {} tree: {}", pattern, tree, e)))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| Error::parse(format!("Bad globs of {} tree: {}", tree, e)))
+}
+
+/// Directory part of glob before its first wildcard, `Editor/Data/il2cpp/libil2cpp` for `Editor/Data/il2cpp/libil2cpp/**`
+fn literal_base(pattern: &str) -> String {
+    let wildcard = pattern.find(['*', '?', '[', '{']).unwrap_or(pattern.len());
+    pattern[..wildcard].rsplit_once('/').map_or("", |e| e.0).to_string()
+}
+
+impl ExtractionFilter {
+    /// Tree names and roots must be unique, empty rules are refused
+    pub fn new(rules: &Vec<TreeRule>, layout: Layout) -> Result<Self> {
+        if rules.is_empty() {
+            return Err(Error::parse("At least one extraction tree is required"));
+        }
+
+        let mut trees: Vec<Tree> = Vec::with_capacity(rules.len());
+        for rule in rules {
+            // `./il2cpp` and `il2cpp/` are the same directory
+            let root = rule.root.components().filter(|e| *e != Component::CurDir).collect::<PathBuf>();
+            if let Some(other) = trees.iter().find(|e| e.name == rule.name || e.root == root) {
+                return Err(Error::parse(format!(
+                    "{} and {} trees would overwrite each other, give every tree its own name and root",
+                    other.name, rule.name
+                )));
+            }
+            trees.push(Tree {
+                name: rule.name.clone(),
+                root,
+                strip_prefix: rule.strip_prefix.trim_matches('/').to_string(),
+                include: build_set(&rule.name, &rule.include)?,
+                exclude: build_set(&rule.name, &rule.exclude)?,
+                bases: rule.include.iter().map(|e| literal_base(e)).collect(),
+            });
+        }
+        Ok(Self { trees, layout })
+    }
+
+    /// `<root>/<major>/<version>` of every tree, first one is the primary libil2cpp tree
+    pub fn output_directories(&self, unity_version: &DownloadableUnity) -> Vec<PathBuf> {
+        self.trees.iter().map(|e| self.layout.tree_directory(&e.root, unity_version)).collect()
+    }
+
+    /// Name of `tree`-th configured tree
+    #[inline]
+    pub fn tree_name(&self, tree: usize) -> &str {
+        &self.trees[tree].name
+    }
+
+    #[inline]
+    pub(crate) fn is_included(&self, tree: usize, path: &str) -> bool {
+        let tree = &self.trees[tree];
+        tree.include.is_match(path) && !tree.exclude.is_match(path)
+    }
+
+    /// Whether anything under archive `directory` may be taken by some tree, so walking editor install can skip the rest
+    pub(crate) fn may_contain(&self, directory: &str) -> bool {
+        let is_within = |path: &str, base: &str| base.is_empty() || path.strip_prefix(base).is_some_and(|e| e.is_empty() || e.starts_with('/'));
+        self.trees
+            .iter()
+            .flat_map(|e| &e.bases)
+            .any(|base| is_within(directory, base) || is_within(base, directory))
+    }
+
+    /// Trees taking normalized archive `path` along with its path inside of them
+    pub(crate) fn route<'a>(&'a self, path: &'a str) -> impl Iterator<Item = (usize, PathBuf)> + 'a {
+        self.trees
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.is_included(*index, path))
+            .map(move |(index, tree)| {
+                let relative = match path.strip_prefix(&tree.strip_prefix) {
+                    Some(rest) if !tree.strip_prefix.is_empty() && (rest.is_empty() || rest.starts_with('/')) => rest.trim_start_matches('/'),
+                    _ => path,
+                };
+                (index, PathBuf::from(relative))
+            })
+    }
+}
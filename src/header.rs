@@ -0,0 +1,1317 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Display},
+    fs,
+    path::Path,
+};
+
+use crate::error::{Error, Result};
+
+/// Size and alignment of pointers on LP64
+const POINTER_SIZE: u64 = 8;
+/// What `__attribute__((aligned))` means on x86-64
+const MAX_ALIGNMENT: u64 = 16;
+
+/// Words which never start type name of their own
+const QUALIFIERS: [&'static str; 16] = [
+    "const",
+    "volatile",
+    "restrict",
+    "__restrict",
+    "__restrict__",
+    "static",
+    "extern",
+    "inline",
+    "__inline",
+    "__inline__",
+    "register",
+    "auto",
+    "__extension__",
+    "_Noreturn",
+    "_Thread_local",
+    "__thread",
+];
+const BUILTIN_WORDS: [&'static str; 11] = [
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "_Bool", "__int128",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Struct,
+    Union,
+    Enum,
+    Typedef,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Struct => write!(f, "struct"),
+            Kind::Union => write!(f, "union"),
+            Kind::Enum => write!(f, "enum"),
+            Kind::Typedef => write!(f, "typedef"),
+        }
+    }
+}
+
+/// Member of struct or union, members of anonymous ones are lifted into parent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Field {
+    /// Members of named nested struct or union are dotted, `data.klassIndex`
+    pub name: String,
+    /// Whitespace-normalized spelling without field name, bit-fields end with `:<width>`
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// Bytes from start of type, unknown once any preceding member has unknown size
+    pub offset: Option<u64>,
+    /// Bit of byte at `offset` bit-field starts at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bit_offset: Option<u8>,
+    /// Bit-fields have none
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Variant {
+    pub name: String,
+    /// Unknown when initializer uses something besides literals and other enumerators
+    pub value: Option<i64>,
+}
+
+/// Typedef, struct, union or enum of preprocessed header with its LP64 layout
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TypeDefinition {
+    pub name: String,
+    pub kind: Kind,
+    pub size: Option<u64>,
+    pub alignment: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<Field>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<Variant>,
+    /// What typedef aliases
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// Types declared by single-header `struct.h`
+///
+/// Tags and typedef names share one namespace since libil2cpp always names them the same,
+/// `struct X` is spelled as `X` and `typedef struct X X;` declares nothing on its own
+#[derive(Debug, Default, Serialize)]
+pub struct Header {
+    pub types: BTreeMap<String, TypeDefinition>,
+}
+
+impl Header {
+    /// Never fails, declarations parser doesn't understand are skipped
+    pub fn parse(source: &str) -> Self {
+        let tokens = tokenize(source);
+        let mut parser = Parser::new(&tokens);
+        parser.translation_unit();
+
+        let mut resolver = Resolver {
+            definitions: &parser.definitions,
+            cache: HashMap::new(),
+            resolving: HashSet::new(),
+        };
+        let types = parser
+            .definitions
+            .iter()
+            .map(|(name, definition)| (name.clone(), resolver.type_definition(name, definition)))
+            .collect();
+        Self { types }
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        Ok(Self::parse(&source))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pack {
+    /// Optionally sets new value after saving current one
+    Push(Option<u64>),
+    Pop,
+    /// `None` restores natural alignment
+    Set(Option<u64>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Text(String),
+    Pack(Pack),
+}
+
+/// `pragma pack(push, p1, 4)` and friends, every other directive is dropped
+fn parse_pack(directive: &str) -> Option<Pack> {
+    let directive = directive.trim().strip_prefix("pragma")?.trim().strip_prefix("pack")?.trim();
+    let arguments = directive.strip_prefix('(')?.strip_suffix(')')?;
+    let arguments = arguments.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()).collect::<Vec<_>>();
+    let value = |e: &&str| e.parse::<u64>().ok();
+    Some(match arguments.first() {
+        None => Pack::Set(None),
+        Some(&"push") => Pack::Push(arguments[1..].iter().find_map(value)),
+        Some(&"pop") => Pack::Pop,
+        Some(e) => Pack::Set(Some(value(e)?)),
+    })
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    const PUNCTUATORS: [&'static str; 11] = ["...", "<<", ">>", "->", "::", "&&", "||", "<=", ">=", "==", "!="];
+
+    let bytes = source.as_bytes();
+    let mut tokens = vec![];
+    let mut line_start = true;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c == b'\n' {
+            line_start = true;
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let line_end = source[i..].find('\n').map_or(bytes.len(), |e| i + e);
+        if c == b'#' && line_start {
+            tokens.extend(parse_pack(&source[i + 1..line_end]).map(Token::Pack));
+            i = line_end;
+            continue;
+        }
+        line_start = false;
+        if source[i..].starts_with("//") {
+            i = line_end;
+            continue;
+        }
+        if source[i..].starts_with("/*") {
+            i = source[i + 2..].find("*/").map_or(bytes.len(), |e| i + e + 4);
+            continue;
+        }
+
+        let start = i;
+        if c.is_ascii_alphanumeric() || c == b'_' {
+            let number = c.is_ascii_digit();
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || (number && bytes[i] == b'.')) {
+                i += 1;
+            }
+        } else if c == b'"' || c == b'\'' {
+            i += 1;
+            while i < bytes.len() && bytes[i] != c {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(bytes.len());
+        } else if let Some(punctuator) = PUNCTUATORS.iter().find(|e| source[i..].starts_with(**e)) {
+            i += punctuator.len();
+        } else {
+            i += source[i..].chars().next().map_or(1, |e| e.len_utf8());
+        }
+        tokens.push(Token::Text(source[start..i].to_string()));
+    }
+    tokens
+}
+
+fn is_identifier(token: &str) -> bool {
+    token.starts_with(|e: char| e.is_ascii_alphabetic() || e == '_') && token.chars().all(|e| e.is_ascii_alphanumeric() || e == '_')
+}
+
+fn is_word(token: &str) -> bool {
+    token.starts_with(|e: char| e.is_ascii_alphanumeric() || e == '_')
+}
+
+/// Joins tokens so that spelling doesn't depend on whitespace, `const char* const*`, `void (*)(int)`
+fn render<S: AsRef<str>>(tokens: &[S]) -> String {
+    let mut rendered = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let token = token.as_ref();
+        if let Some(previous) = i.checked_sub(1).map(|e| tokens[e].as_ref()) {
+            let space = (is_word(previous) && (is_word(token) || token == "(")) || previous == "," || (previous == "*" && is_word(token));
+            if space {
+                rendered.push(' ');
+            }
+        }
+        rendered.push_str(token);
+    }
+    rendered
+}
+
+/// Wrapping integer literal, C suffixes and character constants included
+fn parse_number(token: &str) -> Option<i64> {
+    if let Some(character) = token.strip_prefix('\'').and_then(|e| e.strip_suffix('\'')) {
+        return match character.as_bytes() {
+            [c] => Some(*c as i64),
+            [b'\\', b'n'] => Some(10),
+            [b'\\', b't'] => Some(9),
+            [b'\\', b'0'] => Some(0),
+            [b'\\', c] => Some(*c as i64),
+            _ => None,
+        };
+    }
+    let digits = token.trim_end_matches(['u', 'U', 'l', 'L']);
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse::<u64>().ok()?
+    };
+    Some(value as i64)
+}
+
+fn precedence(operator: &str) -> Option<u8> {
+    match operator {
+        "*" | "/" | "%" => Some(10),
+        "+" | "-" => Some(9),
+        "<<" | ">>" => Some(8),
+        "&" => Some(5),
+        "^" => Some(4),
+        "|" => Some(3),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Attributes {
+    aligned: Option<u64>,
+    packed: bool,
+}
+
+impl Attributes {
+    fn merge(&mut self, other: Attributes) {
+        self.aligned = self.aligned.max(other.aligned);
+        self.packed |= other.packed;
+    }
+}
+
+/// Derived type as seen from declared name outwards, `int *a[3]` is array of pointers
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+    Pointer,
+    /// `None` when length is not constant parser can evaluate
+    Array(Option<u64>),
+    Function,
+}
+
+#[derive(Debug, Clone)]
+enum Base {
+    /// Spelled with keywords only, `unsigned int`
+    Builtin(Option<u64>),
+    /// Typedef name or tag
+    Named(String),
+    /// Anonymous struct, union or enum defined in place
+    Inline(Box<Definition>),
+}
+
+#[derive(Debug, Clone)]
+struct Specifier {
+    base: Base,
+    spelling: Vec<String>,
+    typedef: bool,
+    attributes: Attributes,
+}
+
+#[derive(Debug, Clone)]
+struct Declarator {
+    name: Option<String>,
+    ops: Vec<Op>,
+    spelling: Vec<String>,
+    attributes: Attributes,
+}
+
+#[derive(Debug, Clone)]
+struct Member {
+    /// `None` for anonymous struct or union and unnamed bit-field
+    name: Option<String>,
+    base: Base,
+    ops: Vec<Op>,
+    spelling: String,
+    width: Option<u64>,
+    attributes: Attributes,
+}
+
+#[derive(Debug, Clone)]
+struct Aggregate {
+    kind: Kind,
+    members: Vec<Member>,
+    /// `pragma pack` in effect where it was defined
+    pack: Option<u64>,
+    attributes: Attributes,
+}
+
+#[derive(Debug, Clone)]
+enum Definition {
+    Aggregate(Aggregate),
+    Enum(Vec<Variant>),
+    Typedef { base: Base, ops: Vec<Op>, spelling: String },
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    pack: Option<u64>,
+    pack_stack: Vec<Option<u64>>,
+    /// Enumerators seen so far, array lengths may use them
+    constants: HashMap<String, i64>,
+    definitions: BTreeMap<String, Definition>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            pack: None,
+            pack_stack: vec![],
+            constants: HashMap::new(),
+            definitions: BTreeMap::new(),
+        }
+    }
+
+    /// Applies pragmas on the way, they are never seen by the rest of parser
+    fn peek(&mut self) -> Option<&'a str> {
+        while let Some(Token::Pack(pack)) = self.tokens.get(self.position) {
+            match pack {
+                Pack::Push(value) => {
+                    self.pack_stack.push(self.pack);
+                    if let Some(value) = value {
+                        self.pack = Some(*value);
+                    }
+                }
+                Pack::Pop => self.pack = self.pack_stack.pop().flatten(),
+                Pack::Set(value) => self.pack = *value,
+            }
+            self.position += 1;
+        }
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&'a str> {
+        match self.tokens.get(self.position + offset) {
+            Some(Token::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, token: &str) -> Option<()> {
+        (self.next()? == token).then_some(())
+    }
+
+    /// Tokens up to closing bracket matching already consumed `open`, which is consumed too
+    fn balanced(&mut self, open: &str, close: &str) -> Option<Vec<String>> {
+        let mut depth = 1;
+        let mut tokens = vec![];
+        loop {
+            let token = self.next()?;
+            if token == open {
+                depth += 1;
+            } else if token == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(tokens);
+                }
+            }
+            tokens.push(token.to_string());
+        }
+    }
+
+    /// Skips to `;` ending declaration parser gave up on, stops before `}` closing enclosing body
+    fn recover(&mut self, start: usize) {
+        self.position = start;
+        let mut depth = 0usize;
+        while let Some(token) = self.peek() {
+            match token {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" if depth == 0 => return,
+                ")" | "]" | "}" => depth -= 1,
+                ";" if depth == 0 => {
+                    self.position += 1;
+                    return;
+                }
+                _ => {}
+            }
+            self.position += 1;
+        }
+    }
+
+    fn translation_unit(&mut self) {
+        while self.peek().is_some() {
+            let start = self.position;
+            if self.declaration().is_none() {
+                self.recover(start);
+                // Stray closing bracket at top level
+                if self.position == start {
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    fn evaluate(&self, tokens: &[String]) -> Option<i64> {
+        let mut position = 0;
+        let value = self.expression(tokens, &mut position, 0)?;
+        (position == tokens.len()).then_some(value)
+    }
+
+    fn expression(&self, tokens: &[String], position: &mut usize, minimum: u8) -> Option<i64> {
+        let mut lhs = self.unary(tokens, position)?;
+        while let Some(operator) = tokens.get(*position) {
+            let Some(precedence) = precedence(operator).filter(|e| *e >= minimum) else {
+                break;
+            };
+            *position += 1;
+            let rhs = self.expression(tokens, position, precedence + 1)?;
+            lhs = match operator.as_str() {
+                "*" => lhs.checked_mul(rhs)?,
+                "/" => lhs.checked_div(rhs)?,
+                "%" => lhs.checked_rem(rhs)?,
+                "+" => lhs.checked_add(rhs)?,
+                "-" => lhs.checked_sub(rhs)?,
+                "<<" => lhs.checked_shl(rhs.try_into().ok()?)?,
+                ">>" => lhs.checked_shr(rhs.try_into().ok()?)?,
+                "&" => lhs & rhs,
+                "^" => lhs ^ rhs,
+                _ => lhs | rhs,
+            };
+        }
+        Some(lhs)
+    }
+
+    fn unary(&self, tokens: &[String], position: &mut usize) -> Option<i64> {
+        let token = tokens.get(*position)?;
+        *position += 1;
+        match token.as_str() {
+            "-" => self.unary(tokens, position)?.checked_neg(),
+            "+" => self.unary(tokens, position),
+            "~" => Some(!self.unary(tokens, position)?),
+            "!" => Some((self.unary(tokens, position)? == 0) as i64),
+            "(" => {
+                // Casts to integer types don't change constants libil2cpp uses
+                let cast = tokens.get(*position + 1).is_some_and(|e| e == ")")
+                    && tokens.get(*position).is_some_and(|e| is_identifier(e) && !self.constants.contains_key(e));
+                if cast {
+                    *position += 2;
+                    return self.unary(tokens, position);
+                }
+                let value = self.expression(tokens, position, 0)?;
+                (tokens.get(*position)? == ")").then_some(())?;
+                *position += 1;
+                Some(value)
+            }
+            token if is_identifier(token) => self.constants.get(token).copied(),
+            token => parse_number(token),
+        }
+    }
+
+    /// Any number of `__attribute__((...))`, `_Alignas(...)`, `__declspec(...)` and `__asm__(...)`
+    fn attributes(&mut self) -> Attributes {
+        let mut attributes = Attributes::default();
+        while let Some(token) = self.peek() {
+            let alignas = matches!(token, "_Alignas" | "alignas");
+            if !alignas && !matches!(token, "__attribute__" | "__attribute" | "__declspec" | "__asm__" | "__asm" | "asm") {
+                break;
+            }
+            self.position += 1;
+            if self.peek() != Some("(") {
+                break;
+            }
+            self.position += 1;
+            let Some(inner) = self.balanced("(", ")") else {
+                break;
+            };
+            if alignas {
+                attributes.aligned = attributes.aligned.max(self.evaluate(&inner).map(|e| e as u64));
+                continue;
+            }
+            for (i, word) in inner.iter().enumerate() {
+                match word.as_str() {
+                    "packed" | "__packed__" => attributes.packed = true,
+                    "aligned" | "__aligned__" => {
+                        let value = match inner.get(i + 1).map(|e| e.as_str()) {
+                            Some("(") => {
+                                let end = inner[i + 1..].iter().position(|e| e == ")").map_or(inner.len(), |e| i + 1 + e);
+                                self.evaluate(&inner[i + 2..end]).map(|e| e as u64)
+                            }
+                            _ => Some(MAX_ALIGNMENT),
+                        };
+                        attributes.aligned = attributes.aligned.max(value);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        attributes
+    }
+
+    fn specifier(&mut self) -> Option<Specifier> {
+        let mut typedef = false;
+        let mut attributes = Attributes::default();
+        let mut spelling = vec![];
+        let mut builtin = vec![];
+        let mut base = None;
+        loop {
+            attributes.merge(self.attributes());
+            let Some(token) = self.peek() else {
+                break;
+            };
+            if token == "typedef" {
+                typedef = true;
+            } else if QUALIFIERS.contains(&token) {
+                if matches!(token, "const" | "volatile") {
+                    spelling.push(token.to_string());
+                }
+            } else if BUILTIN_WORDS.contains(&token) && base.is_none() {
+                builtin.push(token);
+                spelling.push(token.to_string());
+            } else if matches!(token, "struct" | "union" | "enum") && base.is_none() && builtin.is_empty() {
+                let (tagged, name) = self.tagged()?;
+                spelling.push(name);
+                base = Some(tagged);
+                continue;
+            } else if is_identifier(token) && base.is_none() && builtin.is_empty() {
+                spelling.push(token.to_string());
+                base = Some(Base::Named(token.to_string()));
+            } else {
+                break;
+            }
+            self.position += 1;
+        }
+
+        let base = match base {
+            Some(base) => base,
+            None if !builtin.is_empty() => Base::Builtin(builtin_size(&builtin)),
+            None => return None,
+        };
+        Some(Specifier {
+            base,
+            spelling,
+            typedef,
+            attributes,
+        })
+    }
+
+    /// `struct`, `union` or `enum` with optional tag and body, tagged bodies are defined right away
+    fn tagged(&mut self) -> Option<(Base, String)> {
+        let keyword = self.next()?;
+        let mut attributes = self.attributes();
+        let tag = self.peek().filter(|e| is_identifier(e)).map(|e| e.to_string());
+        if tag.is_some() {
+            self.position += 1;
+        }
+        attributes.merge(self.attributes());
+
+        let definition = if self.peek() == Some("{") {
+            self.position += 1;
+            let definition = if keyword == "enum" {
+                Definition::Enum(self.enumerators()?)
+            } else {
+                let pack = self.pack;
+                let members = self.members()?;
+                attributes.merge(self.attributes());
+                Definition::Aggregate(Aggregate {
+                    kind: if keyword == "union" { Kind::Union } else { Kind::Struct },
+                    members,
+                    pack,
+                    attributes,
+                })
+            };
+            Some(definition)
+        } else {
+            None
+        };
+
+        Some(match (tag, definition) {
+            (Some(tag), Some(definition)) => {
+                self.definitions.insert(tag.clone(), definition);
+                (Base::Named(tag.clone()), tag)
+            }
+            (Some(tag), None) => (Base::Named(tag.clone()), tag),
+            (None, Some(definition)) => (Base::Inline(Box::new(definition)), keyword.to_string()),
+            (None, None) => return None,
+        })
+    }
+
+    /// Body of enum after `{`, implicit values continue from previous enumerator
+    fn enumerators(&mut self) -> Option<Vec<Variant>> {
+        let mut variants = vec![];
+        let mut next = Some(0i64);
+        loop {
+            let token = self.next()?;
+            if token == "}" {
+                return Some(variants);
+            }
+            if !is_identifier(token) {
+                return None;
+            }
+            self.attributes();
+            let value = if self.peek() == Some("=") {
+                self.position += 1;
+                let mut depth = 0usize;
+                let mut expression = vec![];
+                while let Some(token) = self.peek() {
+                    match token {
+                        "," | "}" if depth == 0 => break,
+                        "(" => depth += 1,
+                        ")" => depth = depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    expression.push(token.to_string());
+                    self.position += 1;
+                }
+                self.evaluate(&expression)
+            } else {
+                next
+            };
+            if let Some(value) = value {
+                self.constants.insert(token.to_string(), value);
+            }
+            next = value.and_then(|e| e.checked_add(1));
+            variants.push(Variant {
+                name: token.to_string(),
+                value,
+            });
+            if self.peek() == Some(",") {
+                self.position += 1;
+            }
+        }
+    }
+
+    /// Body of struct or union after `{`, closing brace included
+    fn members(&mut self) -> Option<Vec<Member>> {
+        let mut members = vec![];
+        loop {
+            match self.peek()? {
+                "}" => {
+                    self.position += 1;
+                    return Some(members);
+                }
+                ";" => self.position += 1,
+                _ => {
+                    let start = self.position;
+                    match self.member_declaration() {
+                        Some(declared) => members.extend(declared),
+                        None => {
+                            self.recover(start);
+                            if self.position == start {
+                                self.position += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn member_declaration(&mut self) -> Option<Vec<Member>> {
+        let specifier = self.specifier()?;
+        if self.peek() == Some(";") {
+            self.position += 1;
+            return Some(match specifier.base {
+                Base::Inline(ref definition) if matches!(**definition, Definition::Aggregate(_)) => vec![Member {
+                    name: None,
+                    base: specifier.base.clone(),
+                    ops: vec![],
+                    spelling: render(&specifier.spelling),
+                    width: None,
+                    attributes: specifier.attributes,
+                }],
+                _ => vec![],
+            });
+        }
+
+        let mut members = vec![];
+        loop {
+            let mut declarator = self.declarator()?;
+            let mut spelling = specifier.spelling.clone();
+            spelling.append(&mut declarator.spelling);
+            let width = if self.peek() == Some(":") {
+                self.position += 1;
+                let mut expression = vec![];
+                while let Some(token) = self.peek().filter(|e| !matches!(*e, "," | ";") && !e.starts_with("__attribute")) {
+                    expression.push(token.to_string());
+                    self.position += 1;
+                }
+                spelling.push(":".into());
+                spelling.extend(expression.iter().cloned());
+                Some(self.evaluate(&expression)? as u64)
+            } else {
+                None
+            };
+            declarator.attributes.merge(self.attributes());
+            declarator.attributes.merge(specifier.attributes);
+            members.push(Member {
+                name: declarator.name,
+                base: specifier.base.clone(),
+                ops: declarator.ops,
+                spelling: render(&spelling),
+                width,
+                attributes: declarator.attributes,
+            });
+            if self.peek() != Some(",") {
+                break;
+            }
+            self.position += 1;
+        }
+        self.expect(";")?;
+        Some(members)
+    }
+
+    fn declarator(&mut self) -> Option<Declarator> {
+        let mut attributes = Attributes::default();
+        let mut spelling = vec![];
+        let mut pointers = 0;
+        loop {
+            attributes.merge(self.attributes());
+            match self.peek() {
+                Some("*") => pointers += 1,
+                Some(qualifier) if QUALIFIERS.contains(&qualifier) => {}
+                _ => break,
+            }
+            spelling.push(self.next()?.to_string());
+        }
+
+        let (name, mut ops) = match self.peek() {
+            Some("(") if matches!(self.peek_at(1), Some("*" | "(" | "^")) => {
+                self.position += 1;
+                let inner = self.declarator()?;
+                self.expect(")")?;
+                attributes.merge(inner.attributes);
+                spelling.push("(".into());
+                spelling.extend(inner.spelling);
+                spelling.push(")".into());
+                (inner.name, inner.ops)
+            }
+            Some(name) if is_identifier(name) && !QUALIFIERS.contains(&name) => {
+                self.position += 1;
+                (Some(name.to_string()), vec![])
+            }
+            _ => (None, vec![]),
+        };
+
+        loop {
+            attributes.merge(self.attributes());
+            match self.peek() {
+                Some("[") => {
+                    self.position += 1;
+                    let length = self.balanced("[", "]")?;
+                    ops.push(Op::Array(match length.is_empty() {
+                        // Flexible array member
+                        true => Some(0),
+                        false => self.evaluate(&length).and_then(|e| u64::try_from(e).ok()),
+                    }));
+                    spelling.push("[".into());
+                    spelling.extend(length);
+                    spelling.push("]".into());
+                }
+                Some("(") => {
+                    self.position += 1;
+                    let parameters = self.balanced("(", ")")?;
+                    ops.push(Op::Function);
+                    spelling.push("(".into());
+                    spelling.extend(parameters);
+                    spelling.push(")".into());
+                }
+                _ => break,
+            }
+        }
+        ops.extend(std::iter::repeat_n(Op::Pointer, pointers));
+
+        Some(Declarator {
+            name,
+            ops,
+            spelling,
+            attributes,
+        })
+    }
+
+    fn declaration(&mut self) -> Option<()> {
+        let specifier = self.specifier()?;
+        // Typedef names given to anonymous body after the first one alias it
+        let mut named: Option<String> = None;
+        loop {
+            if self.peek() == Some(";") {
+                self.position += 1;
+                return Some(());
+            }
+            let declarator = self.declarator()?;
+            self.attributes();
+            match (specifier.typedef, declarator.name) {
+                (true, Some(name)) => {
+                    let definition = match (&specifier.base, declarator.ops.is_empty(), &named) {
+                        // Forward declaration of tag under its own name
+                        (Base::Named(tag), true, _) if *tag == name => None,
+                        (Base::Inline(definition), true, None) => {
+                            named = Some(name.clone());
+                            Some((**definition).clone())
+                        }
+                        (Base::Inline(_), true, Some(first)) => Some(Definition::Typedef {
+                            base: Base::Named(first.clone()),
+                            ops: vec![],
+                            spelling: first.clone(),
+                        }),
+                        _ => {
+                            let mut spelling = specifier.spelling.clone();
+                            spelling.extend(declarator.spelling);
+                            Some(Definition::Typedef {
+                                base: specifier.base.clone(),
+                                ops: declarator.ops,
+                                spelling: render(&spelling),
+                            })
+                        }
+                    };
+                    if let Some(definition) = definition {
+                        self.definitions.insert(name, definition);
+                    }
+                }
+                // Function definition, its body is the only thing skipped without `;`
+                (false, _) if declarator.ops.first() == Some(&Op::Function) && self.peek() == Some("{") => {
+                    self.position += 1;
+                    self.balanced("{", "}")?;
+                    return Some(());
+                }
+                _ => {}
+            }
+            if self.peek() == Some("=") {
+                let start = self.position;
+                self.recover(start);
+                return Some(());
+            }
+            if self.peek() != Some(",") {
+                break;
+            }
+            self.position += 1;
+        }
+        self.expect(";")
+    }
+}
+
+fn builtin_size(words: &[&str]) -> Option<u64> {
+    let has = |word| words.contains(&word);
+    let longs = words.iter().filter(|e| **e == "long").count();
+    Some(match () {
+        _ if has("void") => return None,
+        _ if has("char") || has("_Bool") => 1,
+        _ if has("short") => 2,
+        _ if has("__int128") => 16,
+        _ if has("double") && longs > 0 => 16,
+        _ if has("double") => 8,
+        _ if has("float") => 4,
+        _ if longs > 0 => 8,
+        _ => 4,
+    })
+}
+
+/// Standard typedefs which preprocessed header has lost together with system headers
+fn standard_size(name: &str) -> Option<u64> {
+    Some(match name {
+        "int8_t" | "uint8_t" | "bool" => 1,
+        "int16_t" | "uint16_t" | "char16_t" => 2,
+        "int32_t" | "uint32_t" | "wchar_t" | "char32_t" => 4,
+        "int64_t" | "uint64_t" | "size_t" | "ssize_t" | "intptr_t" | "uintptr_t" | "ptrdiff_t" | "intmax_t" | "uintmax_t" | "off_t" => 8,
+        _ => return None,
+    })
+}
+
+/// `None` on overflow, layout of type that large is unknown
+#[inline]
+fn align_up(value: u64, alignment: u64) -> Option<u64> {
+    value.div_ceil(alignment.max(1)).checked_mul(alignment.max(1))
+}
+
+/// Start and end bit of member placed at `start` bit, `None` on overflow
+fn place(start: u64, size: u64, alignment: u64, width: Option<u64>, packed: bool) -> Option<(u64, u64)> {
+    let alignment = alignment.checked_mul(8)?;
+    let Some(width) = width else {
+        let bit = align_up(start, alignment)?;
+        return Some((bit, bit.checked_add(size.checked_mul(8)?)?));
+    };
+    let unit = size.checked_mul(8)?;
+    let straddles = unit > 0 && width > 0 && start / unit != (start.checked_add(width)? - 1) / unit;
+    // Bit-field starts new unit when it doesn't fit into current one, zero width one always does
+    let bit = match width == 0 || (!packed && straddles) {
+        true => align_up(start, alignment)?,
+        false => start,
+    };
+    Some((bit, bit.checked_add(width)?))
+}
+
+/// Underlying type gcc picks: `unsigned int` or `int` when values fit, `long` otherwise, unknown with unknown values
+fn enum_layout(variants: &[Variant]) -> Option<(u64, u64)> {
+    let values = variants.iter().map(|e| e.value).collect::<Option<Vec<_>>>()?;
+    let (min, max) = (values.iter().min().copied().unwrap_or(0), values.iter().max().copied().unwrap_or(0));
+    let fits = (min >= 0 && max <= u32::MAX as i64) || (min >= i32::MIN as i64 && max <= i32::MAX as i64);
+    Some(if fits { (4, 4) } else { (8, 8) })
+}
+
+/// Computes LP64 layouts the way gcc does on x86-64
+struct Resolver<'a> {
+    definitions: &'a BTreeMap<String, Definition>,
+    /// Size and alignment of named types
+    cache: HashMap<String, Option<(u64, u64)>>,
+    /// Guards against types containing themselves, only possible in broken headers
+    resolving: HashSet<String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn type_definition(&mut self, name: &str, definition: &Definition) -> TypeDefinition {
+        let mut type_definition = TypeDefinition {
+            name: name.to_string(),
+            kind: Kind::Typedef,
+            size: None,
+            alignment: None,
+            fields: vec![],
+            variants: vec![],
+            target: None,
+        };
+        let layout = match definition {
+            Definition::Aggregate(aggregate) => {
+                let (fields, layout) = self.aggregate(aggregate);
+                type_definition.kind = aggregate.kind;
+                type_definition.fields = fields;
+                layout
+            }
+            Definition::Enum(variants) => {
+                type_definition.kind = Kind::Enum;
+                type_definition.variants = variants.clone();
+                enum_layout(variants)
+            }
+            Definition::Typedef { base, ops, spelling } => {
+                type_definition.target = Some(spelling.clone());
+                self.declared(base, ops)
+            }
+        };
+        type_definition.size = layout.map(|e| e.0);
+        type_definition.alignment = layout.map(|e| e.1);
+        type_definition
+    }
+
+    fn named(&mut self, name: &str) -> Option<(u64, u64)> {
+        if let Some(layout) = self.cache.get(name) {
+            return *layout;
+        }
+        let Some(definition) = self.definitions.get(name) else {
+            return standard_size(name).map(|e| (e, e));
+        };
+        if !self.resolving.insert(name.to_string()) {
+            return None;
+        }
+        let layout = self.definition(definition);
+        self.resolving.remove(name);
+        self.cache.insert(name.to_string(), layout);
+        layout
+    }
+
+    fn definition(&mut self, definition: &Definition) -> Option<(u64, u64)> {
+        match definition {
+            Definition::Aggregate(aggregate) => self.aggregate(aggregate).1,
+            Definition::Enum(variants) => enum_layout(variants),
+            Definition::Typedef { base, ops, .. } => self.declared(base, ops),
+        }
+    }
+
+    fn declared(&mut self, base: &Base, ops: &[Op]) -> Option<(u64, u64)> {
+        match ops.first() {
+            None => match base {
+                Base::Builtin(size) => size.map(|e| (e, e)),
+                Base::Named(name) => self.named(name),
+                Base::Inline(definition) => self.definition(definition),
+            },
+            Some(Op::Pointer) => Some((POINTER_SIZE, POINTER_SIZE)),
+            Some(Op::Array(Some(length))) => self
+                .declared(base, &ops[1..])
+                .and_then(|(size, alignment)| Some((size.checked_mul(*length)?, alignment))),
+            Some(Op::Array(None) | Op::Function) => None,
+        }
+    }
+
+    /// Fields with offsets, size and alignment, the latter are unknown once any member has unknown size
+    fn aggregate(&mut self, aggregate: &Aggregate) -> (Vec<Field>, Option<(u64, u64)>) {
+        let union = aggregate.kind == Kind::Union;
+        let pack = if aggregate.attributes.packed { Some(1) } else { aggregate.pack };
+        let mut fields = vec![];
+        // Bits of struct placed so far, or bytes of largest union member
+        let mut position = Some(0u64);
+        let mut alignment = 1;
+
+        for member in &aggregate.members {
+            let layout = self.declared(&member.base, &member.ops).map(|(size, natural)| {
+                let mut alignment = pack.map_or(natural, |e| natural.min(e));
+                if let Some(aligned) = member.attributes.aligned {
+                    alignment = alignment.max(aligned);
+                }
+                (size, alignment)
+            });
+            let start = if union { Some(0) } else { position };
+            let placed = match (start, layout) {
+                (Some(start), Some((size, member_alignment))) => place(start, size, member_alignment, member.width, pack.is_some()),
+                _ => None,
+            };
+            if let Some((_, member_alignment)) = layout.filter(|_| member.width != Some(0)) {
+                alignment = alignment.max(member_alignment);
+            }
+            position = match (union, placed) {
+                (true, Some((_, end))) => position.map(|e| e.max(end.div_ceil(8))),
+                (false, Some((_, end))) => Some(end),
+                (_, None) => None,
+            };
+
+            let offset = placed.map(|e| e.0 / 8);
+            let nested = match &member.base {
+                Base::Inline(definition) if member.ops.is_empty() => match &**definition {
+                    Definition::Aggregate(nested) => Some(self.aggregate(nested).0),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let shift = |mut field: Field| {
+                field.offset = field.offset.zip(offset).and_then(|(a, b)| a.checked_add(b));
+                field
+            };
+            match &member.name {
+                Some(name) => {
+                    fields.push(Field {
+                        name: name.clone(),
+                        ty: member.spelling.clone(),
+                        offset,
+                        bit_offset: member.width.and(placed).map(|e| (e.0 % 8) as u8),
+                        size: layout.filter(|_| member.width.is_none()).map(|e| e.0),
+                    });
+                    fields.extend(nested.into_iter().flatten().map(|mut e| {
+                        e.name = format!("{}.{}", name, e.name);
+                        shift(e)
+                    }));
+                }
+                None => fields.extend(nested.into_iter().flatten().map(shift)),
+            }
+        }
+
+        if let Some(aligned) = aggregate.attributes.aligned {
+            alignment = alignment.max(aligned);
+        }
+        let size = match union {
+            true => position,
+            false => position.map(|e| e.div_ceil(8)),
+        };
+        (fields, size.and_then(|e| Some((align_up(e, alignment)?, alignment))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expected layouts were taken from gcc on x86-64
+    fn layout(header: &Header, name: &str) -> (Option<u64>, Option<u64>) {
+        let type_definition = &header.types[name];
+        (type_definition.size, type_definition.alignment)
+    }
+
+    fn field<'a>(header: &'a Header, name: &str, field: &str) -> &'a Field {
+        header.types[name].fields.iter().find(|e| e.name == field).unwrap()
+    }
+
+    fn offset(header: &Header, name: &str, field_name: &str) -> Option<u64> {
+        field(header, name, field_name).offset
+    }
+
+    #[test]
+    fn pragma_pack() {
+        let header = Header::parse(
+            "#pragma pack(push, p1, 4)\n\
+             typedef struct Packed { char a; int64_t b; double c; } Packed;\n\
+             #pragma pack(pop, p1)\n\
+             typedef struct Natural { char a; int64_t b; } Natural;\n\
+             #pragma pack(2)\n\
+             typedef struct Two { char a; int32_t b; } Two;\n\
+             #pragma pack()\n\
+             typedef struct __attribute__((packed)) Attribute { char a; int32_t b; short c; } Attribute;",
+        );
+        assert_eq!(layout(&header, "Packed"), (Some(20), Some(4)));
+        assert_eq!(offset(&header, "Packed", "b"), Some(4));
+        assert_eq!(offset(&header, "Packed", "c"), Some(12));
+        assert_eq!(layout(&header, "Natural"), (Some(16), Some(8)));
+        assert_eq!(offset(&header, "Natural", "b"), Some(8));
+        assert_eq!(layout(&header, "Two"), (Some(6), Some(2)));
+        assert_eq!(layout(&header, "Attribute"), (Some(7), Some(1)));
+        assert_eq!(offset(&header, "Attribute", "c"), Some(5));
+    }
+
+    #[test]
+    fn bit_fields_crossing_storage_units() {
+        let header =
+            Header::parse("typedef struct Bits { uint8_t a : 5; uint8_t b : 5; uint32_t c : 20; uint32_t d : 20; uint16_t e : 3; int : 0; char f; } Bits;");
+        let position = |name| {
+            let field = field(&header, "Bits", name);
+            (field.offset, field.bit_offset)
+        };
+        assert_eq!(position("a"), (Some(0), Some(0)));
+        assert_eq!(position("b"), (Some(1), Some(0)));
+        assert_eq!(position("c"), (Some(4), Some(0)));
+        assert_eq!(position("d"), (Some(8), Some(0)));
+        assert_eq!(position("e"), (Some(10), Some(4)));
+        assert_eq!(position("f"), (Some(12), None));
+        assert_eq!(field(&header, "Bits", "c").ty, "uint32_t:20");
+        assert_eq!(field(&header, "Bits", "c").size, None);
+        assert_eq!(layout(&header, "Bits"), (Some(16), Some(4)));
+    }
+
+    #[test]
+    fn nested_and_anonymous_members() {
+        let header = Header::parse(
+            "typedef struct Outer { int x; union { char c; double d; }; struct { short s; int i; } inner; char tail; } Outer;\n\
+             typedef struct Il2CppType { union { void* dummy; int32_t klassIndex; } data; unsigned int attrs : 16; } Il2CppType;",
+        );
+        assert_eq!(layout(&header, "Outer"), (Some(32), Some(8)));
+        assert_eq!(offset(&header, "Outer", "c"), Some(8));
+        assert_eq!(offset(&header, "Outer", "d"), Some(8));
+        assert_eq!(offset(&header, "Outer", "inner"), Some(16));
+        assert_eq!(offset(&header, "Outer", "inner.s"), Some(16));
+        assert_eq!(offset(&header, "Outer", "inner.i"), Some(20));
+        assert_eq!(offset(&header, "Outer", "tail"), Some(24));
+        assert_eq!(field(&header, "Outer", "inner").ty, "struct");
+
+        assert_eq!(offset(&header, "Il2CppType", "data.klassIndex"), Some(0));
+        assert_eq!(offset(&header, "Il2CppType", "attrs"), Some(8));
+        assert_eq!(layout(&header, "Il2CppType"), (Some(16), Some(8)));
+    }
+
+    #[test]
+    fn enums() {
+        let header = Header::parse(
+            "typedef enum Small { SMALL_A, SMALL_B = 0x10 | (1 << 2), SMALL_C, SMALL_D = -3, SMALL_E, SMALL_F = SMALL_B + 1 } Small;\n\
+             enum { ANONYMOUS = 7 };\n\
+             typedef struct WithEnum { char c; Small e; char d; int array[ANONYMOUS]; } WithEnum;",
+        );
+        let values = header.types["Small"].variants.iter().map(|e| e.value).collect::<Vec<_>>();
+        assert_eq!(values, [Some(0), Some(20), Some(21), Some(-3), Some(-2), Some(21)]);
+        assert_eq!(header.types["Small"].kind, Kind::Enum);
+        assert_eq!(layout(&header, "Small"), (Some(4), Some(4)));
+        assert_eq!(offset(&header, "WithEnum", "e"), Some(4));
+        assert_eq!(offset(&header, "WithEnum", "d"), Some(8));
+        assert_eq!(layout(&header, "WithEnum"), (Some(40), Some(4)));
+    }
+
+    #[test]
+    fn enum_size_follows_values() {
+        let header = Header::parse(
+            "typedef enum Unsigned { U = 0xffffffff } Unsigned;\n\
+             typedef enum Wide { W = 0x100000000 } Wide;\n\
+             typedef enum Mixed { M1 = -1, M2 = 0x80000000 } Mixed;\n\
+             typedef enum Unknown { X = UNKNOWN } Unknown;\n\
+             typedef struct Holder { char c; Wide w; } Holder;",
+        );
+        assert_eq!(layout(&header, "Unsigned"), (Some(4), Some(4)));
+        assert_eq!(layout(&header, "Wide"), (Some(8), Some(8)));
+        assert_eq!(layout(&header, "Mixed"), (Some(8), Some(8)));
+        assert_eq!(layout(&header, "Unknown"), (None, None));
+        assert_eq!(offset(&header, "Holder", "w"), Some(8));
+    }
+
+    #[test]
+    fn overflowing_layout_is_unknown() {
+        let header = Header::parse(
+            "typedef struct Huge { char x[0x7fffffffffffffff]; int after; } Huge;\n\
+             typedef struct Nested { char c; Huge huge[4]; } Nested;\n\
+             typedef struct Sane { char x[16]; int after; } Sane;",
+        );
+        // Layout is computed in bits, so even the array itself doesn't fit
+        assert_eq!(offset(&header, "Huge", "x"), None);
+        assert_eq!(offset(&header, "Huge", "after"), None);
+        assert_eq!(layout(&header, "Huge"), (None, None));
+        assert_eq!(layout(&header, "Nested"), (None, None));
+        assert_eq!(layout(&header, "Sane"), (Some(20), Some(4)));
+    }
+
+    #[test]
+    fn typedef_chains() {
+        let header = Header::parse(
+            "typedef int32_t Index; typedef Index Alias; typedef Alias Triple[3];\n\
+             typedef struct Chained Chained;\n\
+             struct Chained { char c; Triple values; Alias last; };\n\
+             typedef Chained Renamed;\n\
+             typedef const Chained* Pointer;",
+        );
+        assert_eq!(header.types["Alias"].target.as_deref(), Some("Index"));
+        assert_eq!(header.types["Triple"].target.as_deref(), Some("Alias[3]"));
+        assert_eq!(layout(&header, "Triple"), (Some(12), Some(4)));
+        assert_eq!(header.types["Chained"].kind, Kind::Struct);
+        assert_eq!(layout(&header, "Chained"), (Some(20), Some(4)));
+        assert_eq!(offset(&header, "Chained", "values"), Some(4));
+        assert_eq!(offset(&header, "Chained", "last"), Some(16));
+        assert_eq!(layout(&header, "Renamed"), (Some(20), Some(4)));
+        assert_eq!(header.types["Pointer"].target.as_deref(), Some("const Chained*"));
+        assert_eq!(layout(&header, "Pointer"), (Some(8), Some(8)));
+    }
+
+    #[test]
+    fn pointer_and_array_members() {
+        let header = Header::parse(
+            "typedef struct Members { char c; void* p; char* names[3]; void (*callback)(int); int matrix[2][3]; char flexible[]; } Members;\n\
+             typedef void* (*InvokerMethod)(void*, const char * const *);",
+        );
+        assert_eq!(layout(&header, "Members"), (Some(72), Some(8)));
+        assert_eq!(offset(&header, "Members", "p"), Some(8));
+        assert_eq!(offset(&header, "Members", "names"), Some(16));
+        assert_eq!(offset(&header, "Members", "callback"), Some(40));
+        assert_eq!(offset(&header, "Members", "matrix"), Some(48));
+        assert_eq!(offset(&header, "Members", "flexible"), Some(72));
+        assert_eq!(field(&header, "Members", "names").ty, "char*[3]");
+        assert_eq!(field(&header, "Members", "names").size, Some(24));
+        assert_eq!(field(&header, "Members", "callback").ty, "void (*)(int)");
+        assert_eq!(field(&header, "Members", "matrix").size, Some(24));
+        assert_eq!(header.types["InvokerMethod"].target.as_deref(), Some("void*(*)(void*, const char* const*)"));
+        assert_eq!(layout(&header, "InvokerMethod"), (Some(8), Some(8)));
+    }
+
+    #[test]
+    fn unknown_sizes_propagate() {
+        let header = Header::parse(
+            "typedef struct Holder { int a; Missing m; int after; } Holder; typedef struct Opaque Opaque; typedef struct User { Opaque* p; } User;",
+        );
+        assert_eq!(offset(&header, "Holder", "a"), Some(0));
+        // Alignment of unknown type is unknown too
+        assert_eq!(offset(&header, "Holder", "m"), None);
+        assert_eq!(offset(&header, "Holder", "after"), None);
+        assert_eq!(layout(&header, "Holder"), (None, None));
+        assert!(!header.types.contains_key("Opaque"));
+        assert_eq!(layout(&header, "User"), (Some(8), Some(8)));
+    }
+
+    #[test]
+    fn recovers_from_unparsable_declarations() {
+        let header = Header::parse(
+            "} ) ]\n\
+             static inline int helper(int x) { if (x) { return 1; } return 0; }\n\
+             _Static_assert(sizeof(int) == 4, \"int\");\n\
+             extern int variable = { 1, 2 };\n\
+             void function(const char* name) __attribute__((deprecated));\n\
+             typedef struct Broken { int a; int b : unknown width; ) ; int c; } Broken;\n\
+             42 garbage;\n\
+             typedef struct After { int a; } After;",
+        );
+        let names = header.types["Broken"].fields.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["a", "c"]);
+        assert_eq!(offset(&header, "Broken", "c"), Some(4));
+        assert_eq!(layout(&header, "After"), (Some(4), Some(4)));
+        assert_eq!(header.types.keys().collect::<Vec<_>>(), ["After", "Broken"]);
+    }
+
+    #[test]
+    fn whitespace_does_not_change_spelling() {
+        let compact = Header::parse("typedef struct S { const char*const* names; unsigned   int x:3; } S;");
+        let spaced = Header::parse("typedef struct S\n{\n    const char * const * names ;\n    unsigned int x : 3 ;\n} S ;");
+        assert_eq!(compact.types, spaced.types);
+        assert_eq!(field(&compact, "S", "names").ty, "const char* const*");
+        assert_eq!(field(&compact, "S", "x").ty, "unsigned int:3");
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt::{self, Display};
+
+use crate::error::{Error, Result};
+use crate::header::{Field, Header, TypeDefinition};
+use crate::layout::Layout;
+use crate::struct_diff::{compare_fields, compare_type, Change};
+use crate::version_parser::DownloadableUnity;
+
+/// `Il2CppClass` or its field `Il2CppClass.klass`, nested fields are dotted further, `Il2CppType.data.klassIndex`
+#[derive(Debug, Clone)]
+pub enum Subject {
+    Type(String),
+    Field { type_name: String, field: String },
+}
+
+impl From<&str> for Subject {
+    fn from(name: &str) -> Self {
+        match name.split_once('.') {
+            Some((type_name, field)) => Subject::Field {
+                type_name: type_name.to_string(),
+                field: field.to_string(),
+            },
+            None => Subject::Type(name.to_string()),
+        }
+    }
+}
+
+impl Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subject::Type(name) => write!(f, "{}", name),
+            Subject::Field { type_name, field } => write!(f, "{}.{}", type_name, field),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum State {
+    Type(TypeDefinition),
+    Field(Field),
+}
+
+impl Subject {
+    fn state(&self, header: &Header) -> Option<State> {
+        match self {
+            Subject::Type(name) => header.types.get(name).cloned().map(State::Type),
+            Subject::Field { type_name, field } => header
+                .types
+                .get(type_name)
+                .and_then(|e| e.fields.iter().find(|e| e.name == *field))
+                .cloned()
+                .map(State::Field),
+        }
+    }
+}
+
+fn changes(old: &State, new: &State) -> Vec<Change> {
+    match (old, new) {
+        (State::Type(old), State::Type(new)) => compare_type(Some(old), Some(new)),
+        (State::Field(old), State::Field(new)) => {
+            let mut changes = vec![];
+            compare_fields(std::slice::from_ref(old), std::slice::from_ref(new), &mut changes);
+            changes
+        }
+        _ => vec![],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// Already there in the first version having `struct.h` at all
+    Present,
+    Introduced,
+    Changed,
+    Removed,
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Present => write!(f, "present"),
+            Event::Introduced => write!(f, "introduced"),
+            Event::Changed => write!(f, "changed"),
+            Event::Removed => write!(f, "removed"),
+        }
+    }
+}
+
+/// Consecutive versions subject is identical in, or absent from
+#[derive(Debug)]
+pub struct Span {
+    pub first: DownloadableUnity,
+    pub last: DownloadableUnity,
+    pub versions: usize,
+    /// How subject got this way in [`Self::first`], `None` while it is yet to be introduced
+    pub event: Option<Event>,
+    /// What changed compared to previous span, only set for [`Event::Changed`]
+    pub changes: Vec<Change>,
+}
+
+/// Spans in version order, empty when no version has `struct.h`
+pub fn history(layout: &Layout, unity_versions: &[DownloadableUnity], subject: &Subject) -> Result<Vec<Span>> {
+    let mut unity_versions = unity_versions
+        .iter()
+        .filter(|e| layout.version_directory(e).join("struct.h").exists())
+        .collect::<Vec<_>>();
+    unity_versions.sort();
+    unity_versions.dedup();
+
+    let headers = unity_versions.into_iter().map(|unity_version| {
+        let header = Header::read(&layout.version_directory(unity_version).join("struct.h")).map_err(|e| e.with_version(&unity_version.inner))?;
+        Ok((unity_version, header))
+    });
+    spans(headers, subject)
+}
+
+/// [`history`] over headers already read, in version order
+fn spans<'a>(headers: impl IntoIterator<Item = Result<(&'a DownloadableUnity, Header)>>, subject: &Subject) -> Result<Vec<Span>> {
+    let mut spans: Vec<Span> = vec![];
+    // State of last span, `None` while subject is absent
+    let mut current: Option<State> = None;
+    for header in headers {
+        let (unity_version, header) = header?;
+        let state = subject.state(&header);
+
+        let event = match (spans.is_empty(), &current, &state) {
+            (true, _, Some(_)) => Some(Event::Present),
+            (true, _, None) => None,
+            (false, None, Some(_)) => Some(Event::Introduced),
+            (false, Some(_), None) => Some(Event::Removed),
+            (false, Some(old), Some(new)) if old != new => Some(Event::Changed),
+            (false, ..) => {
+                let span = spans.last_mut().unwrap();
+                span.last = unity_version.clone();
+                span.versions += 1;
+                continue;
+            }
+        };
+        let changes = match (&current, &state) {
+            (Some(old), Some(new)) => changes(old, new),
+            _ => vec![],
+        };
+        spans.push(Span {
+            first: unity_version.clone(),
+            last: unity_version.clone(),
+            versions: 1,
+            event,
+            changes,
+        });
+        current = state;
+    }
+
+    if !spans.iter().any(|e| e.event.is_some()) && !spans.is_empty() {
+        return Err(Error::parse(format!("{} is not declared by any struct.h", subject)));
+    }
+    Ok(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Release;
+
+    fn unity_version(version: &str) -> DownloadableUnity {
+        DownloadableUnity::try_from(Release {
+            version: version.into(),
+            changeset: "0".into(),
+            release_date: None,
+            stream: None,
+            entitlements: vec![],
+        })
+        .unwrap()
+    }
+
+    /// Versions `2020.1.<index>f1` with given headers
+    fn history_of(sources: &[&str], subject: &str) -> Result<Vec<Span>> {
+        let unity_versions = (0..sources.len()).map(|e| unity_version(&format!("2020.1.{}f1", e))).collect::<Vec<_>>();
+        let headers = unity_versions
+            .iter()
+            .zip(sources)
+            .map(|(unity_version, source)| Ok((unity_version, Header::parse(source))));
+        spans(headers, &Subject::from(subject))
+    }
+
+    fn events(spans: &[Span]) -> Vec<(Option<Event>, String, String)> {
+        spans.iter().map(|e| (e.event, e.first.name.clone(), e.last.name.clone())).collect()
+    }
+
+    fn span(event: Option<Event>, first: usize, last: usize) -> (Option<Event>, String, String) {
+        (event, format!("2020.1.{}f1", first), format!("2020.1.{}f1", last))
+    }
+
+    #[test]
+    fn present_changed_removed_and_reintroduced() {
+        let v1 = "typedef struct S { int a; } S;";
+        let v2 = "typedef struct S { int a; int b; } S;";
+        let spans = history_of(&[v1, v1, v2, "", v1], "S").unwrap();
+        assert_eq!(
+            events(&spans),
+            [
+                span(Some(Event::Present), 0, 1),
+                span(Some(Event::Changed), 2, 2),
+                span(Some(Event::Removed), 3, 3),
+                span(Some(Event::Introduced), 4, 4),
+            ]
+        );
+        assert_eq!(spans[0].versions, 2);
+        assert_eq!(
+            spans[1].changes,
+            [
+                Change::SizeChanged { from: Some(4), to: Some(8) },
+                Change::FieldAdded {
+                    field: "b".into(),
+                    ty: "int".into(),
+                    offset: Some(4),
+                },
+            ]
+        );
+        assert!(spans[2].changes.is_empty() && spans[3].changes.is_empty());
+    }
+
+    #[test]
+    fn introduced_after_absence() {
+        let spans = history_of(&["", "", "typedef struct S { int a; } S;"], "S").unwrap();
+        assert_eq!(events(&spans), [span(None, 0, 1), span(Some(Event::Introduced), 2, 2)]);
+    }
+
+    #[test]
+    fn field_history() {
+        let spans = history_of(
+            &[
+                "typedef struct S { int a; } S;",
+                "typedef struct S { int a; int b; } S;",
+                "typedef struct S { int b; } S;",
+            ],
+            "S.b",
+        )
+        .unwrap();
+        assert_eq!(
+            events(&spans),
+            [span(None, 0, 0), span(Some(Event::Introduced), 1, 1), span(Some(Event::Changed), 2, 2)]
+        );
+        assert_eq!(
+            spans[2].changes,
+            [Change::OffsetChanged {
+                field: "b".into(),
+                from: Some(4),
+                to: Some(0),
+                from_bit: None,
+                to_bit: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn layout_only_change_is_described() {
+        // Field is spelled the same while typedef behind it got wider, so only its size and alignment tell versions apart
+        let spans = history_of(
+            &[
+                "typedef int32_t Index; typedef struct S { Index i; } S;",
+                "typedef int64_t Index; typedef struct S { Index i; } S;",
+            ],
+            "S",
+        )
+        .unwrap();
+        assert_eq!(spans[1].event, Some(Event::Changed));
+        assert_eq!(
+            spans[1].changes,
+            [
+                Change::SizeChanged { from: Some(4), to: Some(8) },
+                Change::AlignmentChanged { from: Some(4), to: Some(8) },
+                Change::FieldResized {
+                    field: "i".into(),
+                    from: Some(4),
+                    to: Some(8),
+                },
+            ]
+        );
+
+        let spans = history_of(&["typedef enum E { A = 1, B = 2 } E;", "typedef enum E { B = 2, A = 1 } E;"], "E").unwrap();
+        assert_eq!(spans[1].changes, [Change::LayoutChanged]);
+    }
+
+    #[test]
+    fn undeclared_subject_is_error() {
+        let error = history_of(&["typedef struct S { int a; } S;"], "Missing").unwrap_err();
+        assert!(matches!(error, Error::Parse { .. }), "{:?}", error);
+        assert!(history_of(&[], "S").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    fs::{self, File},
+    io::ErrorKind,
+    os::unix,
+    path::{Path, PathBuf},
+};
+use unity_version::UnityVersion;
+
+use crate::catalog::{Release, UNKNOWN_CHANGESET};
+use crate::downloader;
+use crate::error::{Error, Result};
+use crate::filter::ExtractionFilter;
+use crate::manifest::{TreeContents, VersionManifest};
+use crate::store::ContentStore;
+use crate::version_parser::{is_version_string, DownloadableUnity};
+use crate::writer::{copy_hashed, is_inside_tree, LibIl2CppWriter, WriteError};
+
+/// Relative to editor root, which is directory holding `Editor`
+const LIBIL2CPP_PATH: &'static str = "Editor/Data/il2cpp/libil2cpp";
+const PLAYBACK_ENGINES_PATH: &'static str = "Editor/Data/PlaybackEngines";
+
+/// `Unity-2021.3.5f1.tar.xz` as served by unity, any `-`/`_` separated part is accepted
+fn version_from_archive_name(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.strip_suffix(".tar.xz")?;
+    stem.split(['-', '_']).find(|e| is_version_string(e)).map(str::to_string)
+}
+
+/// Unity Hub installs into `.../Editor/<version>/`, otherwise `ivy.xml` of playback engines tells it
+fn version_from_editor(root: &Path) -> Option<String> {
+    let from_path = root.ancestors().filter_map(|e| e.file_name()?.to_str()).find(|e| is_version_string(e));
+    if let Some(version) = from_path {
+        return Some(version.to_string());
+    }
+
+    for engine in fs::read_dir(root.join(PLAYBACK_ENGINES_PATH)).ok()?.flatten() {
+        let Ok(content) = fs::read_to_string(engine.path().join("ivy.xml")) else {
+            continue;
+        };
+        let Some(start) = content.find("unityVersion=\"").map(|e| e + "unityVersion=\"".len()) else {
+            continue;
+        };
+        let version = content[start..].split('"').next()?;
+        if is_version_string(version) {
+            return Some(version.to_string());
+        }
+    }
+
+    None
+}
+
+/// Accepts editor root, its `Editor` directory or `libil2cpp` itself
+fn editor_root(path: &Path) -> Option<PathBuf> {
+    let path = fs::canonicalize(path).ok()?;
+    path.ancestors().take(5).find(|e| e.join(LIBIL2CPP_PATH).is_dir()).map(Path::to_path_buf)
+}
+
+/// Everything copied is recorded into `contents` for version manifest
+struct EditorCopy<'a> {
+    filter: &'a ExtractionFilter,
+    store: Option<&'a ContentStore>,
+    output_directories: Vec<PathBuf>,
+    contents: Vec<TreeContents>,
+}
+
+/// Walks installed editor the same way extractor walks archive, so every configured tree is filled
+fn copy_editor(root: &Path, directory: &Path, copy: &mut EditorCopy) -> Result<()> {
+    let path = root.join(directory);
+    for entry in fs::read_dir(&path).map_err(|e| Error::io(&path, e))? {
+        let entry = entry.map_err(|e| Error::io(&path, e))?;
+        let relative = directory.join(entry.file_name());
+        let archive_path = relative.to_string_lossy();
+        let file_type = entry.file_type().map_err(|e| Error::io(entry.path(), e))?;
+        if file_type.is_dir() && !copy.filter.may_contain(&archive_path) {
+            continue;
+        }
+
+        let routes = copy.filter.route(&archive_path).collect::<Vec<_>>();
+        if !routes.is_empty() && relative.to_str().is_none() {
+            return Err(WriteError::NonUtf8Path(entry.path()).into());
+        }
+        for (tree, tree_relative) in routes {
+            let output = copy.output_directories[tree].join(&tree_relative);
+            let key = tree_relative.to_string_lossy().to_string();
+            if file_type.is_dir() {
+                fs::create_dir_all(&output).map_err(|e| Error::io(&output, e))?;
+                continue;
+            }
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+            }
+            if file_type.is_symlink() {
+                let target = fs::read_link(entry.path()).map_err(|e| Error::io(entry.path(), e))?;
+                if !is_inside_tree(&tree_relative, &target) {
+                    return Err(WriteError::LinkEscape { path: entry.path(), target }.into());
+                }
+                unix::fs::symlink(&target, &output).map_err(|e| Error::io(&output, e))?;
+                copy.contents[tree].symlinks.insert(key, target.to_string_lossy().to_string());
+                continue;
+            }
+
+            let mut file = File::open(entry.path()).map_err(|e| Error::io(entry.path(), e))?;
+            let digest = match copy.store {
+                Some(store) => store.put(&mut file, &output),
+                None => copy_hashed(&mut file, &output),
+            }?;
+            copy.contents[tree].files.insert(key, digest);
+        }
+        if file_type.is_dir() {
+            copy_editor(root, &relative, copy)?;
+        }
+    }
+    Ok(())
+}
+
+/// Places libil2cpp (and other configured trees) from local `.tar.xz` archive or installed editor into `<major>/<version>`
+pub fn import(path: &Path, filter: &ExtractionFilter, store: Option<&ContentStore>) -> Result<UnityVersion> {
+    let (version, root) = if path.is_file() {
+        let version = version_from_archive_name(path)
+            .ok_or_else(|| Error::parse("Couldn't detect unity version, name archive Unity-<version>.tar.xz").with_path(path))?;
+        (version, None)
+    } else {
+        let root = editor_root(path).ok_or_else(|| Error::parse("Neither editor nor its libil2cpp directory").with_path(path))?;
+        let version = version_from_editor(&root).ok_or_else(|| Error::parse("Couldn't detect unity version of editor").with_path(&root))?;
+        (version, Some(root))
+    };
+
+    let unity_version = DownloadableUnity::try_from(Release {
+        version,
+        changeset: UNKNOWN_CHANGESET.into(),
+        release_date: None,
+        stream: None,
+        entitlements: vec![],
+    })?;
+    let output_directories = filter.output_directories(&unity_version);
+    if output_directories[0].exists() {
+        return Err(Error::io(&output_directories[0], ErrorKind::AlreadyExists.into()));
+    }
+    let created = output_directories.iter().map(|e| !e.exists()).collect::<Vec<_>>();
+
+    let result = match root {
+        Some(root) => {
+            let mut copy = EditorCopy {
+                filter,
+                store,
+                output_directories: output_directories.clone(),
+                contents: output_directories.iter().map(|_| TreeContents::default()).collect(),
+            };
+            copy_editor(&root, Path::new(""), &mut copy).map(|_| {
+                let trees = copy
+                    .contents
+                    .into_iter()
+                    .enumerate()
+                    .map(|(tree, e)| (filter.tree_name(tree).to_string(), e))
+                    .collect();
+                (None, trees)
+            })
+        }
+        None => {
+            let archive = File::open(path).map_err(|e| Error::io(path, e))?;
+            LibIl2CppWriter::new(&unity_version, filter, archive)
+                .with_store(store)
+                .write()
+                .map_err(Error::from)
+                .and_then(|trees| Ok((Some(downloader::hash_archive(path)?), trees)))
+        }
+    }
+    .and_then(|(archive, trees)| {
+        let manifest = VersionManifest {
+            version: unity_version.name.clone(),
+            changeset: UNKNOWN_CHANGESET.into(),
+            archive,
+            trees,
+        };
+        if let Some(store) = store {
+            store.write_manifest(&unity_version, &manifest.trees)?;
+        }
+        manifest.write(&output_directories[0])
+    });
+    if let Err(e) = result {
+        output_directories.iter().zip(created).filter(|(_, created)| *created).for_each(|(e, _)| {
+            let _ = fs::remove_dir_all(e);
+        });
+        return Err(e);
+    }
+
+    Ok(unity_version.inner)
+}
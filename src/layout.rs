@@ -0,0 +1,115 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::version_parser::DownloadableUnity;
+
+const CATALOG_NAME: &'static str = "versions.json";
+const PROBE_CACHE_NAME: &'static str = "probes.json";
+const DOWNLOADS_NAME: &'static str = "downloads";
+const TABLE_NAME: &'static str = "VERSIONS.md";
+const STRUCT_CHANGES_NAME: &'static str = "struct-changes";
+
+/// Every path the tool reads or writes is resolved against single root, working directory by default
+#[derive(Debug, Clone)]
+pub struct Layout {
+    root: PathBuf,
+    /// Root of primary libil2cpp tree relative to [`Self::root`]
+    primary: PathBuf,
+}
+
+impl Layout {
+    /// `primary` is root of first configured tree, which headers, table and diff work with
+    pub fn new(root: impl Into<PathBuf>, primary: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            primary: primary.into(),
+        }
+    }
+
+    /// Directory every other path is relative to
+    #[inline]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `<root>/<tree root>/<major>/<version>`, absolute tree root is kept as is
+    ///
+    /// Version is spelled as unity does in installer URLs (`2022.3.10f1c1`), same as [`Self::part`]
+    pub fn tree_directory(&self, tree_root: &Path, unity_version: &DownloadableUnity) -> PathBuf {
+        self.root.join(tree_root).join(unity_version.inner.major.to_string()).join(&unity_version.name)
+    }
+
+    /// Primary tree of version relative to root, `2021/2021.3.5f1` with default config
+    pub fn relative_version_directory(&self, unity_version: &DownloadableUnity) -> PathBuf {
+        self.primary
+            .components()
+            .filter(|e| *e != Component::CurDir)
+            .collect::<PathBuf>()
+            .join(unity_version.inner.major.to_string())
+            .join(&unity_version.name)
+    }
+
+    /// Primary tree of version, [`Self::relative_version_directory`] under root
+    pub fn version_directory(&self, unity_version: &DownloadableUnity) -> PathBuf {
+        self.root.join(self.relative_version_directory(unity_version))
+    }
+
+    /// Directory holding `<major>/<version>` directories of primary tree
+    pub fn versions_root(&self) -> PathBuf {
+        self.root.join(&self.primary)
+    }
+
+    /// Releases saved by last online run, see [`crate::catalog`]
+    pub fn catalog(&self) -> PathBuf {
+        self.root.join(CATALOG_NAME)
+    }
+
+    /// Which changesets have linux installer, see [`crate::downloader::ProbeCache`]
+    pub fn probe_cache(&self) -> PathBuf {
+        self.root.join(PROBE_CACHE_NAME)
+    }
+
+    /// Directory of `.part` files
+    pub fn downloads(&self) -> PathBuf {
+        self.root.join(DOWNLOADS_NAME)
+    }
+
+    /// Partially downloaded archive, kept between runs so download resumes
+    pub fn part(&self, unity_version: &DownloadableUnity) -> PathBuf {
+        self.downloads().join(format!("Unity-{}.tar.xz.part", unity_version.name))
+    }
+
+    /// Version table generated by `table` command
+    pub fn table(&self) -> PathBuf {
+        self.root.join(TABLE_NAME)
+    }
+
+    /// `struct.diff` or `api.diff`
+    pub fn diff(&self, name: &str) -> PathBuf {
+        self.root.join(format!("{}.diff", name))
+    }
+
+    /// `struct-changes.md` or `struct-changes.json`
+    pub fn struct_changes(&self, extension: &str) -> PathBuf {
+        self.root.join(format!("{}.{}", STRUCT_CHANGES_NAME, extension))
+    }
+
+    /// How file placed into `directory` should link to primary tree of version
+    pub fn link_from(&self, directory: &Path, unity_version: &DownloadableUnity) -> PathBuf {
+        let same = |a: &Path, b: &Path| {
+            a.components()
+                .filter(|e| *e != Component::CurDir)
+                .eq(b.components().filter(|e| *e != Component::CurDir))
+        };
+        if same(directory, &self.root) {
+            return self.relative_version_directory(unity_version);
+        }
+        let path = self.version_directory(unity_version);
+        std::path::absolute(&path).unwrap_or(path)
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new(".", ".")
+    }
+}
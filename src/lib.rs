@@ -0,0 +1,175 @@
+//! Downloads Unity linux editors, extracts libil2cpp sources out of them and
+//! builds single-header structs, API and version table from what was extracted.
+//!
+//! Network facing functions are async and expect tokio runtime, every one of them
+//! has `_blocking` twin which spins up its own runtime instead.
+
+#![feature(io_error_more)]
+#![deny(unsafe_code)]
+
+use colour::*;
+use scheduler::{dictionary_size, read_stream_start};
+use std::{
+    fs,
+    io::{self, Read},
+    sync::Arc,
+};
+use tokio::{runtime::Runtime, sync::oneshot};
+
+pub mod catalog;
+pub mod config;
+pub mod diff;
+pub mod downloader;
+pub mod error;
+pub mod filter;
+pub mod header;
+pub mod history;
+pub mod import;
+pub mod layout;
+pub mod manifest;
+mod scheduler;
+pub mod selection;
+pub mod skip;
+pub mod store;
+pub mod struct_diff;
+pub mod table;
+mod unidiff;
+pub mod version_parser;
+pub mod writer;
+
+pub use config::Config;
+pub use diff::generate_single_header;
+pub use error::{Error, Result};
+pub use filter::ExtractionFilter;
+pub use layout::Layout;
+pub use manifest::VersionManifest;
+pub use scheduler::Scheduler;
+pub use selection::VersionSelection;
+pub use store::ContentStore;
+pub use table::create_table;
+pub use version_parser::DownloadableUnity;
+pub use writer::LibIl2CppWriter;
+
+fn runtime() -> Result<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| Error::io("tokio runtime", e))
+}
+
+/// Lists every release unity publishes, sorted and deduplicated
+pub async fn fetch_versions(config: &Config) -> Result<Vec<DownloadableUnity>> {
+    let releases = downloader::fetch_versions(config).await?;
+    Ok(version_parser::parse_unity_versions(releases))
+}
+
+/// [`fetch_versions`] on its own runtime
+pub fn fetch_versions_blocking(config: &Config) -> Result<Vec<DownloadableUnity>> {
+    runtime()?.block_on(fetch_versions(config))
+}
+
+/// Extractor follows `.part` file while it is being downloaded, output directories it created are removed if either side fails
+pub async fn download_and_extract(
+    config: &Config,
+    scheduler: &Scheduler,
+    filter: &ExtractionFilter,
+    store: Option<&ContentStore>,
+    unity_version: &DownloadableUnity,
+) -> Result<VersionManifest> {
+    let progress = Arc::new(downloader::Progress::default());
+    let part = config.layout().part(unity_version);
+    let output_directories = filter.output_directories(unity_version);
+    let (started, download_started) = oneshot::channel();
+
+    // Existing trees may hold headers or earlier extraction, only directories created here are removed on failure.
+    // Partial mark makes tree cut off by crash tell apart from one extracted before manifests existed
+    let created = output_directories.iter().map(|e| !e.exists()).collect::<Vec<_>>();
+    let partial = output_directories[0].join(manifest::PARTIAL_NAME);
+    fs::create_dir_all(&output_directories[0])
+        .and_then(|_| fs::write(&partial, ""))
+        .map_err(|e| Error::io(&partial, e).with_version(&unity_version.inner))?;
+    let _ = fs::remove_file(output_directories[0].join(manifest::MANIFEST_NAME));
+
+    let download = async {
+        let _slot = scheduler.download_slot().await;
+        let _ = started.send(());
+        downloader::download_using_changeset(config, unity_version, &progress).await
+    };
+    let extraction = async {
+        // Don't hold extractor waiting for download which haven't even started
+        let _ = download_started.await;
+        let mut reader = downloader::PartReader::open(&part, progress.clone()).map_err(|e| Error::io(&part, e))?;
+        // Memory decoder needs is told by dictionary size in the first block header, so wait until it is downloaded
+        let (start, reader) = tokio::task::spawn_blocking(move || (read_stream_start(&mut reader), reader))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+        let _slot = scheduler.extraction_slot(dictionary_size(&start)).await;
+        cyan_ln!("Decompressing {}, this will take a while...", unity_version);
+        let reader = io::Cursor::new(start).chain(reader);
+        let unity_version = unity_version.clone();
+        // Writer runs on its own thread while download keeps going on this one, so it gets its own copies
+        let filter = filter.clone();
+        let store = store.cloned();
+        tokio::task::spawn_blocking(move || {
+            LibIl2CppWriter::new(&unity_version, &filter, reader)
+                .with_store(store.as_ref())
+                .write()
+                .map_err(|e| Error::from(e).with_version(&unity_version.inner))
+        })
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    };
+    let (downloaded, extracted) = tokio::join!(download, extraction);
+
+    let error = match (downloaded, extracted) {
+        (Ok(archive), Ok(trees)) => {
+            let _ = fs::remove_file(part);
+            let manifest = VersionManifest {
+                version: unity_version.name.clone(),
+                changeset: unity_version.changeset.clone(),
+                archive: Some(archive),
+                trees,
+            };
+            // Store manifest is written only for versions whose archive matched its digest
+            let stored = match store {
+                Some(store) => store
+                    .write_manifest(unity_version, &manifest.trees)
+                    .map_err(|e| Error::from(e).with_version(unity_version)),
+                None => Ok(()),
+            };
+            match stored.and_then(|_| manifest.write(&output_directories[0])) {
+                Ok(_) => {
+                    let _ = fs::remove_file(&partial);
+                    return Ok(manifest);
+                }
+                Err(e) => e,
+            }
+        }
+        // Either archive is complete but unusable or extractor gave up on it first, resuming won't help
+        (Ok(_), Err(e)) => {
+            let _ = fs::remove_file(part);
+            e
+        }
+        (Err(_), Err(e)) if progress.is_abandoned() => {
+            let _ = fs::remove_file(part);
+            e
+        }
+        // Keep `.part` file so next run resumes it
+        (Err(e), _) => e,
+    };
+    output_directories.iter().zip(created).filter(|(_, created)| *created).for_each(|(e, _)| {
+        let _ = fs::remove_dir_all(e);
+    });
+    Err(error)
+}
+
+/// [`download_and_extract`] on its own runtime
+pub fn download_and_extract_blocking(
+    config: &Config,
+    scheduler: &Scheduler,
+    filter: &ExtractionFilter,
+    store: Option<&ContentStore>,
+    unity_version: &DownloadableUnity,
+) -> Result<VersionManifest> {
+    runtime()?.block_on(download_and_extract(config, scheduler, filter, store, unity_version))
+}
@@ -1,152 +1,461 @@
-#![feature(io_error_more)]
 #![feature(async_closure)]
 #![deny(unsafe_code)]
 
-use std::fs;
-
-use args::Arguments;
+use args::{Arguments, Command, SelectionArguments};
 use clap::Parser;
 use colour::*;
-use diff::{diff, generate_single_header, PostProcessError};
-use futures::future::join_all;
-use table::create_table;
+use futures::{stream, StreamExt};
+use libil2cpp_parser::{
+    catalog, config,
+    diff::{diff, PostProcessError},
+    download_and_extract, downloader, generate_single_header,
+    history::{history, Event, Subject},
+    import, manifest,
+    skip::SkipList,
+    struct_diff::diff_structs,
+    table::{create_markdown, read_metadata_version},
+    version_parser, Config, ContentStore, DownloadableUnity, Error, ExtractionFilter, Layout, Result, Scheduler, VersionManifest, VersionSelection,
+};
+use std::{fs, path::Path, process::ExitCode};
 use tokio;
-use unity_version::{UnityVersion, UnityVersionType};
-use version_parser::DownloadableUnity;
 
 mod args;
-mod diff;
-mod downloader;
-mod table;
-mod version_parser;
-mod writer;
-
-async fn decompress_and_flush<'a>(unity_version: DownloadableUnity, buffer: Vec<u8>) -> Option<DownloadableUnity> {
-    let writer = writer::LibIl2CppWriter::new(&unity_version.inner, buffer);
-    cyan_ln!("Decompressing editor, this will take a while...");
-    match writer.write() {
-        Ok(_) => {
-            green_ln!("✔ Successfully extracted libil2cpp source for {}", unity_version);
-            Some(unity_version)
-        }
-        Err(e) => {
-            red_ln!("✘ Failed to extract due {}", e);
-            panic!();
-        }
-    }
-}
 
-async fn stage1(unity_versions: &mut Vec<DownloadableUnity>) {
-    let unity201745f1 = UnityVersion::new(2017, 4, 5, UnityVersionType::Final, 1);
+/// Every failure is printed where it happens, the first one decides exit code
+#[derive(Default)]
+struct Failures(Option<Error>);
+
+impl Failures {
+    fn record(&mut self, e: Error) {
+        self.0.get_or_insert(e);
+    }
 
-    let unity201810b2 = UnityVersion::new(2018, 1, 0, UnityVersionType::Beta, 2);
-    let unity201814f1 = UnityVersion::new(2018, 1, 4, UnityVersionType::Final, 1);
+    fn exit_code(&self) -> ExitCode {
+        self.0.as_ref().map_or(ExitCode::SUCCESS, Error::exit_code)
+    }
+}
 
-    let unity201820b2 = UnityVersion::new(2018, 2, 0, UnityVersionType::Beta, 2);
-    let unity201820b8 = UnityVersion::new(2018, 2, 0, UnityVersionType::Beta, 8);
+/// Downloads every version which isn't skipped or extracted yet
+async fn extract(
+    config: &Config,
+    scheduler: &Scheduler,
+    filter: &ExtractionFilter,
+    store: Option<&ContentStore>,
+    unity_versions: &mut Vec<DownloadableUnity>,
+    failures: &mut Failures,
+) {
+    let skip_list = match SkipList::load(config.skip_list.as_deref()) {
+        Ok(skip_list) => skip_list,
+        Err(e) => {
+            red_ln!("✘ {}", e);
+            failures.record(e);
+            return;
+        }
+    };
+    println!("Using skip list revision {}", skip_list.revision);
 
-    let mut tasks = vec![];
-    for unity_version in unity_versions.iter_mut() {
+    let mut queue = vec![];
+    for (index, unity_version) in unity_versions.iter_mut().enumerate() {
         let inner = &unity_version.inner;
 
-        if (*inner <= unity201745f1) || (*inner >= unity201810b2 && *inner <= unity201814f1) || (*inner >= unity201820b2 && *inner <= unity201820b8) {
+        if let Some(reason) = skip_list.reason(inner) {
+            yellow_ln!("Skipping {} as {}", inner, reason);
             continue;
         }
-        if std::path::Path::new(format!("{}/{}", inner.major, inner.version()).as_str()).exists() {
+        // Newly configured tree makes version downloaded again, so does crash before manifest was written
+        let output_directories = filter.output_directories(unity_version);
+        let mut manifest = VersionManifest::read(&output_directories[0]);
+        if manifest.is_err() && VersionManifest::is_adoptable(&output_directories[0]) {
+            manifest = adopt(filter, store, unity_version);
+        }
+        if manifest.is_ok() && output_directories.iter().all(|e| e.exists()) {
             green_ln!("✔ Already downloaded {}", inner);
             unity_version.is_downloaded = true;
             continue;
         }
-
-        match downloader::is_available_for_linux(inner).await {
-            Ok(true) => {}
-            Ok(false) | Err(_) => {
-                yellow_ln!("Skipping {} as it don't have linux editor", inner);
-                continue;
-            }
+        if manifest.is_err() && output_directories[0].exists() {
+            yellow_ln!("{} was left partial, downloading it again", inner);
         }
+        queue.push(index);
+    }
 
-        match downloader::download_using_changeset(&unity_version).await {
-            Ok(buffer) => {
-                let coro = decompress_and_flush(unity_version.clone(), buffer);
-                // Usually download is slower than decompressing, unless you have 1GiB connection
-                // Let's hope it won't reach OOM
-                tasks.push(tokio::spawn(coro));
+    let cache = downloader::ProbeCache::load(&config.layout());
+    let cache = &cache;
+    let versions = &*unity_versions;
+    let downloaded = stream::iter(queue)
+        .map(|index| async move {
+            let unity_version = &versions[index];
+            match downloader::is_available_for_linux(config, cache, unity_version).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    yellow_ln!("Skipping {} as it don't have linux editor", unity_version.inner);
+                    return Ok(None);
+                }
+                Err(e) => {
+                    yellow_ln!("Skipping {} as {}", unity_version.inner, e);
+                    return Err(e);
+                }
             }
-            Err(e) => red_ln!(
-                "✘ Failed to download due {} this usually means that unity have replaced this version with newer one",
-                e
-            ),
-        }
-    }
-    if tasks.len() > 0 {
-        yellow_ln_bold!("Suspending execution until decompression task will be finished");
-        join_all(tasks.into_iter()).await.into_iter().for_each(|e| {
-            if let Ok(Some(unity_version)) = e {
-                let non_cloned = unity_versions.iter_mut().find(|u| **u == unity_version).unwrap();
-                non_cloned.is_downloaded = true;
-                println!("Marking {} as downloaded", non_cloned);
+
+            match download_and_extract(config, scheduler, filter, store, unity_version).await {
+                Ok(_) => {
+                    green_ln!("✔ Successfully extracted libil2cpp source for {}", unity_version);
+                    Ok(Some(index))
+                }
+                Err(e) if e.is_changeset_removed() => {
+                    red_ln!("✘ Failed to download due {}, unity have replaced this version with newer one", e);
+                    Err(e)
+                }
+                Err(e) => {
+                    red_ln!("✘ Failed to download due {}", e);
+                    Err(e)
+                }
             }
-        });
+        })
+        .buffer_unordered(scheduler.queue_size())
+        .collect::<Vec<_>>()
+        .await;
+
+    for result in downloaded {
+        match result {
+            Ok(Some(index)) => unity_versions[index].is_downloaded = true,
+            Ok(None) => {}
+            Err(e) => failures.record(e),
+        }
     }
 }
 
-fn stage2(unity_versions: &mut Vec<DownloadableUnity>) {
+fn table(layout: &Layout, unity_versions: &mut Vec<DownloadableUnity>, output: &Path) -> Result<()> {
     unity_versions.iter_mut().for_each(|e| e.is_downloaded = true);
-    let table = create_table(unity_versions)
-        .map(|e| format!("<details><summary>Expand Unity {}</summary>\n\n{}</details>", e.0, e.1))
-        .collect::<Vec<_>>()
-        .join("\n");
-    let md = format!(
-        "This file was autogenerated by libil2cpp-parser.
-
-{}",
-        table
-    );
-    fs::write("VERSIONS.md", md).unwrap();
+    let directory = output.parent().unwrap_or(Path::new("."));
+    let markdown = create_markdown(layout, directory, unity_versions)?;
+    fs::write(output, markdown).map_err(|e| Error::io(output, e))
 }
 
-fn stage3(unity_versions: &Vec<DownloadableUnity>) {
+fn headers(layout: &Layout, unity_versions: &Vec<DownloadableUnity>, failures: &mut Failures) {
     for unity_version in unity_versions {
-        match generate_single_header(&unity_version.inner) {
+        match generate_single_header(layout, unity_version) {
             Ok(_) => {
                 green_ln!("✔ Sucessfully preprocessed {} headers", unity_version)
             }
-            Err(PostProcessError::NotDownloaded) => {
+            Err(Error::Preprocess {
+                reason: PostProcessError::NotDownloaded,
+                ..
+            }) => {
                 yellow_ln_bold!("{} is not downloaded, not processing", unity_version)
             }
-            Err(PostProcessError::AlreadyPreprocessed) => {}
-            Err(PostProcessError::InvocationFailed) => {
-                red_ln!("✘ Failed to invoke preprocessor for {}", unity_version)
-            }
-            Err(PostProcessError::NonUtf8Output) => {
-                red_ln!("✘ Failed to parse preprocessor output due non-utf8 characters for {}", unity_version)
+            Err(Error::Preprocess {
+                reason: PostProcessError::AlreadyPreprocessed,
+                ..
+            }) => {}
+            Err(e) => {
+                red_ln!("✘ Failed to preprocess {}", e);
+                failures.record(e);
             }
         }
     }
-    println!("Diffing headers...");
-    diff();
+}
+
+/// Prints release metadata along with what was extracted for it
+fn lookup(config: &Config, filter: &ExtractionFilter, unity_versions: &Vec<DownloadableUnity>, version: &str) -> Result<()> {
+    let unity_version = unity_versions
+        .iter()
+        .find(|e| e.name == version || e.inner.version() == version)
+        .ok_or_else(|| Error::parse(format!("{} is not a known release", version)).with_version(version))?;
+
+    cyan_ln!("{}", unity_version);
+    println!("    Channel: {:?}", unity_version.channel());
+    println!("    Released: {}", unity_version.release_day().unwrap_or("-"));
+    println!("    Stream: {}", unity_version.stream.as_deref().unwrap_or("-"));
+    if !unity_version.entitlements.is_empty() {
+        println!("    Entitlements: {}", unity_version.entitlements.join(", "));
+    }
+    if unity_version.changeset != catalog::UNKNOWN_CHANGESET {
+        println!("    Installer: {}", downloader::installer_url(config, unity_version));
+    }
+
+    let root = config.layout().version_directory(unity_version);
+    match VersionManifest::read(&filter.output_directories(unity_version)[0]) {
+        Ok(_) => {
+            let metadata_version = read_metadata_version(&root).map(|e| e.to_string()).unwrap_or_else(|_| "unknown".into());
+            println!("    Extracted into {}, metadata version {}", root.display(), metadata_version);
+        }
+        Err(_) if VersionManifest::is_adoptable(&root) => println!("    Extracted into {} without manifest, extract or verify adopts it", root.display()),
+        Err(_) => println!("    Not extracted"),
+    }
+    Ok(())
+}
+
+/// Prints spans of versions type or field stays the same in, along with what changed between them
+fn print_history(layout: &Layout, unity_versions: &Vec<DownloadableUnity>, name: &str) -> Result<()> {
+    let subject = Subject::from(name);
+    let spans = history(layout, unity_versions, &subject)?;
+    if spans.is_empty() {
+        yellow_ln_bold!("No version has struct.h, run headers first");
+        return Ok(());
+    }
+
+    cyan_ln!("{}", subject);
+    for span in spans {
+        let versions = match span.versions {
+            1 => span.first.name.clone(),
+            count => format!("{} .. {} ({} versions)", span.first.name, span.last.name, count),
+        };
+        match span.event {
+            None => println!("    {}: absent", versions),
+            Some(Event::Present) => println!("    {}: present", versions),
+            Some(Event::Introduced) => green_ln!("    {}: introduced in {}", versions, span.first.name),
+            Some(Event::Removed) => red_ln!("    {}: removed in {}", versions, span.first.name),
+            Some(Event::Changed) => yellow_ln!("    {}: changed in {}", versions, span.first.name),
+        }
+        span.changes.iter().for_each(|e| println!("        - {}", e));
+    }
+    Ok(())
+}
+
+/// Writes manifest for trees extracted before manifests existed, so they are neither downloaded again nor reported broken
+fn adopt(filter: &ExtractionFilter, store: Option<&ContentStore>, unity_version: &DownloadableUnity) -> Result<VersionManifest> {
+    let adopted = VersionManifest::adopt(filter, store, unity_version).map_err(|e| e.with_version(&unity_version.inner));
+    match &adopted {
+        Ok(_) => green_ln!("✔ Adopted {} extracted without manifest", unity_version.inner),
+        Err(e) => red_ln!("✘ Failed to adopt existing tree: {}", e),
+    }
+    adopted
+}
+
+/// Drops manifest of every partial or tampered version so extract downloads it again
+fn verify(filter: &ExtractionFilter, store: Option<&ContentStore>, unity_versions: &Vec<DownloadableUnity>, failures: &mut Failures) {
+    let mut broken = 0;
+    for unity_version in unity_versions {
+        let directory = &filter.output_directories(unity_version)[0];
+        let mut manifest = VersionManifest::read(directory);
+        if manifest.is_err() && VersionManifest::is_adoptable(directory) {
+            manifest = adopt(filter, store, unity_version);
+        }
+        let problems = match manifest {
+            Ok(manifest) => manifest.verify(filter, unity_version, store),
+            Err(_) => vec!["no manifest, extraction didn't finish".into()],
+        };
+        if problems.is_empty() {
+            green_ln!("✔ {} is intact", unity_version.inner);
+            continue;
+        }
+
+        broken += 1;
+        red_ln!("✘ {} is broken:", unity_version.inner);
+        problems.iter().take(10).for_each(|e| println!("    {}", e));
+        if problems.len() > 10 {
+            println!("    ...and {} more", problems.len() - 10);
+        }
+        let _ = fs::remove_file(directory.join(manifest::MANIFEST_NAME));
+        failures.record(Error::integrity("trees don't match manifest").with_version(&unity_version.inner));
+    }
+
+    if broken > 0 {
+        yellow_ln_bold!("{} versions will be downloaded again by extract", broken);
+    }
+}
+
+fn load_config(args: &Arguments) -> Result<Config> {
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None if Path::new(config::DEFAULT_CONFIG_PATH).exists() => Config::load(Path::new(config::DEFAULT_CONFIG_PATH))?,
+        None => Config::default(),
+    };
+    if let Some(url) = &args.graphql_url {
+        config.graphql_url = url.clone();
+    }
+    if let Some(url) = &args.download_url {
+        config.download_url = url.clone();
+    }
+    if let Some(url) = &args.china_download_url {
+        config.china_download_url = url.clone();
+    }
+    if let Some(path) = &args.store {
+        config.store = Some(path.clone());
+    }
+    if let Some(path) = &args.root {
+        config.root = path.clone();
+    }
+    Ok(config)
+}
+
+/// Releases from network, or from versions.json and existing directories with `--offline`
+async fn load_versions(args: &Arguments, config: &Config) -> Result<Vec<DownloadableUnity>> {
+    let layout = config.layout();
+    let releases = if args.offline {
+        match catalog::load(&layout) {
+            Ok(releases) => releases,
+            Err(e) => {
+                yellow_ln!("{}, falling back to existing directories", e);
+                catalog::scan_directories(&layout)
+            }
+        }
+    } else {
+        let releases = downloader::fetch_versions(config).await?;
+        if let Err(e) = catalog::save(&layout, &releases) {
+            red_ln!("✘ Failed to save versions for offline use: {}", e);
+        }
+        releases
+    };
+    let unity_versions = version_parser::parse_unity_versions(releases);
+    green_ln!("Successfully parsed {} versions", unity_versions.len());
+    Ok(unity_versions)
+}
+
+fn load_selection(args: &SelectionArguments) -> Result<VersionSelection> {
+    Ok(VersionSelection {
+        patterns: args.versions.iter().map(|e| e.parse()).collect::<Result<_>>()?,
+        streams: args.streams.clone(),
+        metadata_versions: args.metadata_versions.clone(),
+        latest_patch: args.latest_patch,
+    })
+}
+
+/// Config, tree rules, store and selection are needed by everything, so failing any of them ends the run
+fn setup(args: &Arguments) -> Result<(Config, ExtractionFilter, Option<ContentStore>, VersionSelection)> {
+    let config = load_config(args)?;
+    let filter = ExtractionFilter::new(&config.trees, config.layout())?;
+    let store = match &config.store {
+        Some(path) => Some(ContentStore::open(path)?),
+        None => None,
+    };
+    let selection = load_selection(&args.selection)?;
+    Ok((config, filter, store, selection))
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
-async fn main() {
+async fn main() -> ExitCode {
     let args = Arguments::parse();
-    let pairs = downloader::fetch_versions().await;
-    let mut unity_versions = version_parser::parse_unity_versions(pairs);
-    assert!(unity_versions.len() > 0);
-    green_ln!("Successfully parsed {} versions", unity_versions.len());
-    if args.stage_1 {
-        println!("Stage 1: Downloading all available unity versions");
-        stage1(&mut unity_versions).await;
-    }
+    let (config, filter, store, selection) = match setup(&args) {
+        Ok(setup) => setup,
+        Err(e) => {
+            red_ln!("✘ {}", e);
+            return e.exit_code();
+        }
+    };
+    let layout = config.layout();
+    let mut failures = Failures::default();
 
-    if args.stage_2 {
-        println!("Stage 2: Building markdown file");
-        stage2(&mut unity_versions);
+    // Only these need release metadata, the rest work with what is on disk
+    let unity_versions = match &args.command {
+        Command::Extract { .. } if args.offline => {
+            red_ln!("✘ Extract requires network, it can't run in offline mode");
+            return ExitCode::FAILURE;
+        }
+        Command::Fetch if args.offline => {
+            red_ln!("✘ Fetch requires network, it can't run in offline mode");
+            return ExitCode::FAILURE;
+        }
+        Command::Fetch | Command::Extract { .. } | Command::Table { .. } | Command::Lookup { .. } => load_versions(&args, &config).await,
+        Command::Headers | Command::Diff | Command::History { .. } | Command::Verify => {
+            catalog::scan_known_directories(&layout).map(version_parser::parse_unity_versions)
+        }
+        Command::Import { .. } | Command::StoreStats => Ok(vec![]),
+    };
+    // Catalog is saved whole no matter what was selected
+    let unity_versions = match &args.command {
+        Command::Fetch => unity_versions,
+        _ => unity_versions.and_then(|e| selection.apply(&layout, e)),
+    };
+    let mut unity_versions = match unity_versions {
+        Ok(unity_versions) => unity_versions,
+        Err(e) => {
+            red_ln!("✘ Failed to list versions: {}", e);
+            return e.exit_code();
+        }
+    };
+    if unity_versions.is_empty() && !matches!(args.command, Command::Import { .. } | Command::StoreStats) {
+        red_ln!("✘ No versions to work with");
+        return ExitCode::FAILURE;
     }
 
-    if args.stage_3 {
-        println!("Stage 3: Building single-header IL2CPP structs and diffing 'em");
-        stage3(&unity_versions);
+    match &args.command {
+        Command::Fetch => green_ln!("✔ Saved versions into {}", layout.catalog().display()),
+        Command::Extract {
+            jobs,
+            extract_jobs,
+            memory_budget,
+        } => {
+            let scheduler = Scheduler::new(*jobs, *extract_jobs, *memory_budget);
+            extract(&config, &scheduler, &filter, store.as_ref(), &mut unity_versions, &mut failures).await;
+        }
+        Command::Table { output } => {
+            let output = output.clone().unwrap_or_else(|| layout.table());
+            match table(&layout, &mut unity_versions, &output) {
+                Ok(_) => green_ln!("✔ Written {}", output.display()),
+                Err(e) => {
+                    red_ln!("✘ Failed to write {}: {}", output.display(), e);
+                    failures.record(e);
+                }
+            }
+        }
+        Command::Headers => headers(&layout, &unity_versions, &mut failures),
+        Command::Diff => {
+            if let Err(e) = diff(&layout, &unity_versions) {
+                red_ln!("✘ Failed to diff headers: {}", e);
+                failures.record(e);
+            }
+            if let Err(e) = diff_structs(&layout, &unity_versions) {
+                red_ln!("✘ Failed to compare structs: {}", e);
+                failures.record(e);
+            }
+        }
+        Command::Lookup { version } => {
+            if let Err(e) = lookup(&config, &filter, &unity_versions, version) {
+                red_ln!("✘ {}", e);
+                failures.record(e);
+            }
+        }
+        Command::History { name } => {
+            if let Err(e) = print_history(&layout, &unity_versions, name) {
+                red_ln!("✘ {}", e);
+                failures.record(e);
+            }
+        }
+        Command::Verify => verify(&filter, store.as_ref(), &unity_versions, &mut failures),
+        Command::Import { paths } => {
+            for path in paths {
+                match import::import(path, &filter, store.as_ref()) {
+                    Ok(unity_version) => green_ln!("✔ Imported libil2cpp source for {} from {}", unity_version, path.display()),
+                    Err(e) => {
+                        red_ln!("✘ Failed to import {}: {}", path.display(), e);
+                        failures.record(e);
+                    }
+                }
+            }
+        }
+        Command::StoreStats => {
+            let Some(store) = &store else {
+                red_ln!("✘ No store configured, pass --store or set store in config");
+                return ExitCode::FAILURE;
+            };
+            match store.stats() {
+                Ok(stats) => {
+                    let mib = |size: u64| size as f64 / (1024.0 * 1024.0);
+                    let saved = stats.logical_size.saturating_sub(stats.stored_size);
+                    let percent = if stats.logical_size > 0 {
+                        saved as f64 * 100.0 / stats.logical_size as f64
+                    } else {
+                        0.0
+                    };
+                    green_ln!(
+                        "{} versions reference {:.1} MiB, {} unique files take {:.1} MiB, saved {:.1} MiB ({:.1}%)",
+                        stats.versions,
+                        mib(stats.logical_size),
+                        stats.objects,
+                        mib(stats.stored_size),
+                        mib(saved),
+                        percent
+                    );
+                }
+                Err(e) => {
+                    red_ln!("✘ Failed to read store: {}", e);
+                    failures.record(e);
+                }
+            }
+        }
     }
+
+    failures.exit_code()
 }
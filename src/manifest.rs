@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::error::{Error, Result};
+use crate::filter::ExtractionFilter;
+use crate::store::ContentStore;
+use crate::version_parser::DownloadableUnity;
+
+pub const MANIFEST_NAME: &'static str = "manifest.json";
+/// Lies in primary tree while extraction runs, tree having it but no manifest was cut off
+pub const PARTIAL_NAME: &'static str = "extraction.partial";
+/// Written into primary tree by the tool itself, so manifest doesn't list them
+const GENERATED_NAMES: [&'static str; 5] = [MANIFEST_NAME, "manifest.json.tmp", PARTIAL_NAME, "struct.h", "api.h"];
+
+/// Digest of editor archive as it came from network
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ArchiveDigest {
+    pub url: String,
+    pub size: u64,
+    pub sha256: String,
+    pub md5: String,
+    /// Taken from `unity-<version>-linux.ini` when unity publishes it for this exact archive
+    pub published_md5: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FileDigest {
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl FileDigest {
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut file, &mut hasher)?;
+        Ok(Self {
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
+
+/// What extractor placed into single tree, paths are relative to it
+#[derive(Serialize, Deserialize, Default)]
+pub struct TreeContents {
+    pub files: BTreeMap<String, FileDigest>,
+    #[serde(default)]
+    pub symlinks: BTreeMap<String, String>,
+}
+
+/// Written into primary tree once extraction finishes, version without it is partial
+#[derive(Serialize, Deserialize)]
+pub struct VersionManifest {
+    pub version: String,
+    pub changeset: String,
+    /// Missing for versions imported from installed editor or adopted from before manifests existed
+    pub archive: Option<ArchiveDigest>,
+    /// Keyed by tree name from config
+    pub trees: BTreeMap<String, TreeContents>,
+}
+
+impl VersionManifest {
+    /// Manifest of primary tree `directory`, failing means version is partial
+    pub fn read(directory: &Path) -> Result<Self> {
+        let path = directory.join(MANIFEST_NAME);
+        let content = fs::read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+        serde_json::from_str(&content).map_err(|e| Error::parse(e).with_path(&path))
+    }
+
+    /// Goes through temporary file so crash never leaves half-written manifest behind
+    pub fn write(&self, directory: &Path) -> Result<()> {
+        let path = directory.join(MANIFEST_NAME);
+        let temporary = directory.join(GENERATED_NAMES[1]);
+        let content = serde_json::to_string_pretty(self).map_err(|e| Error::parse(e).with_path(&path))?;
+
+        let mut file = File::create(&temporary).map_err(|e| Error::io(&temporary, e))?;
+        file.write_all(content.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|e| Error::io(&temporary, e))?;
+        fs::rename(&temporary, &path).map_err(|e| Error::io(&path, e))
+    }
+
+    /// Primary tree was extracted before manifests existed: it has sources, but neither manifest nor partial mark
+    pub fn is_adoptable(directory: &Path) -> bool {
+        let has = |name| directory.join(name).exists();
+        let has_sources = fs::read_dir(directory)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .any(|e| !GENERATED_NAMES.iter().any(|name| e.file_name() == *name));
+        has_sources && !has(MANIFEST_NAME) && !has(PARTIAL_NAME)
+    }
+
+    /// Records existing trees as they are so they aren't downloaded again, files go into `store` when there is one
+    ///
+    /// Trees missing on disk are left out, extract fills them later
+    pub fn adopt(filter: &ExtractionFilter, store: Option<&ContentStore>, unity_version: &DownloadableUnity) -> Result<Self> {
+        let output_directories = filter.output_directories(unity_version);
+        let mut trees = BTreeMap::new();
+        for (tree, directory) in output_directories.iter().enumerate() {
+            if !directory.is_dir() {
+                continue;
+            }
+            let mut paths = vec![];
+            walk(directory, Path::new(""), &mut paths).map_err(|e| Error::io(directory, e))?;
+
+            let mut contents = TreeContents::default();
+            for relative in paths {
+                if tree == 0 && GENERATED_NAMES.contains(&relative.as_str()) {
+                    continue;
+                }
+                let path = directory.join(&relative);
+                let metadata = fs::symlink_metadata(&path).map_err(|e| Error::io(&path, e))?;
+                if metadata.is_symlink() {
+                    let target = fs::read_link(&path).map_err(|e| Error::io(&path, e))?;
+                    contents.symlinks.insert(relative, target.to_string_lossy().to_string());
+                    continue;
+                }
+                let digest = match store {
+                    Some(store) => {
+                        let mut file = File::open(&path).map_err(|e| Error::io(&path, e))?;
+                        store.put(&mut file, &path)?
+                    }
+                    None => FileDigest::of_file(&path).map_err(|e| Error::io(&path, e))?,
+                };
+                contents.files.insert(relative, digest);
+            }
+            trees.insert(filter.tree_name(tree).to_string(), contents);
+        }
+
+        let manifest = Self {
+            version: unity_version.name.clone(),
+            changeset: unity_version.changeset.clone(),
+            archive: None,
+            trees,
+        };
+        if let Some(store) = store {
+            store.write_manifest(unity_version, &manifest.trees)?;
+        }
+        manifest.write(&output_directories[0])?;
+        Ok(manifest)
+    }
+
+    /// Describes every missing, changed or unlisted file, empty means trees are exactly as extracted
+    ///
+    /// With `store` objects behind listed files are rehashed too
+    pub fn verify(&self, filter: &ExtractionFilter, unity_version: &DownloadableUnity, store: Option<&ContentStore>) -> Vec<String> {
+        let mut problems = vec![];
+        let mut objects = BTreeMap::new();
+        for (tree, directory) in filter.output_directories(unity_version).iter().enumerate() {
+            let name = filter.tree_name(tree);
+            let Some(contents) = self.trees.get(name) else {
+                problems.push(format!("{} tree was never extracted", name));
+                continue;
+            };
+
+            for (relative, expected) in &contents.files {
+                let path = directory.join(relative);
+                match FileDigest::of_file(&path) {
+                    Ok(actual) if actual == *expected => {}
+                    Ok(actual) if actual.size != expected.size => {
+                        problems.push(format!("{} is {} bytes instead of {}", path.display(), actual.size, expected.size))
+                    }
+                    Ok(_) => problems.push(format!("{} content has changed", path.display())),
+                    Err(e) => problems.push(format!("{} is unreadable: {}", path.display(), e)),
+                }
+                objects.insert(&expected.sha256, expected);
+            }
+            for (relative, target) in &contents.symlinks {
+                let path = directory.join(relative);
+                match fs::read_link(&path) {
+                    Ok(actual) if actual == Path::new(target) => {}
+                    Ok(actual) => problems.push(format!("{} points to {} instead of {}", path.display(), actual.display(), target)),
+                    Err(e) => problems.push(format!("{} is not a link: {}", path.display(), e)),
+                }
+            }
+
+            let mut unlisted = vec![];
+            if let Err(e) = walk(directory, Path::new(""), &mut unlisted) {
+                problems.push(format!("{} is unreadable: {}", directory.display(), e));
+            }
+            unlisted
+                .into_iter()
+                .filter(|e| !contents.files.contains_key(e) && !contents.symlinks.contains_key(e))
+                .filter(|e| tree != 0 || !GENERATED_NAMES.contains(&e.as_str()))
+                .for_each(|e| problems.push(format!("{} is not in manifest", directory.join(e).display())));
+        }
+
+        if let Some(store) = store {
+            problems.extend(objects.into_values().filter_map(|e| store.verify_object(e)));
+        }
+        problems
+    }
+}
+
+/// Collects every file and symlink under `directory` prefixed by `relative`, directories themselves are skipped
+fn walk(directory: &Path, relative: &Path, paths: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let relative = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), &relative, paths)?;
+        } else {
+            paths.push(relative.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
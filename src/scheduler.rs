@@ -0,0 +1,183 @@
+use std::io::Read;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Tar and copy buffers on top of xz dictionary, which decoder keeps whole in memory
+const EXTRACTION_OVERHEAD_MIB: u32 = 4;
+/// Assumed when dictionary size can't be read from stream, the one of `-9` preset
+const DEFAULT_DICTIONARY_SIZE: u64 = 64 << 20;
+const XZ_MAGIC: &'static [u8] = b"\xfd7zXZ\0";
+const XZ_STREAM_HEADER_SIZE: usize = 12;
+const LZMA2_FILTER_ID: u64 = 0x21;
+
+/// Reads up to `size` bytes, short only at end of stream or on error which the decoder is left to report
+fn read_up_to(reader: &mut impl Read, buffer: &mut Vec<u8>, size: usize) {
+    while buffer.len() < size {
+        let mut chunk = vec![0; size - buffer.len()];
+        match reader.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+    }
+}
+
+/// Stream header and first block header of xz archive, they have to be fed to decoder before the rest of `reader`
+pub(crate) fn read_stream_start(reader: &mut impl Read) -> Vec<u8> {
+    let mut start = vec![];
+    read_up_to(reader, &mut start, XZ_STREAM_HEADER_SIZE + 1);
+    if let Some(&size) = start.get(XZ_STREAM_HEADER_SIZE) {
+        read_up_to(reader, &mut start, XZ_STREAM_HEADER_SIZE + (size as usize + 1) * 4);
+    }
+    start
+}
+
+/// Variable length integer of xz headers, 7 bits per byte
+fn read_vli(bytes: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..63).step_by(7) {
+        let byte = *bytes.get(*position)?;
+        *position += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// LZMA2 dictionary size of first block, every block of archive made by single `xz` run uses the same one
+pub(crate) fn dictionary_size(start: &[u8]) -> Option<u64> {
+    if !start.starts_with(XZ_MAGIC) {
+        return None;
+    }
+    let block = start.get(XZ_STREAM_HEADER_SIZE..)?;
+    // Zero is index indicator, stream has no blocks at all
+    let size = match *block.first()? {
+        0 => return None,
+        e => (e as usize + 1) * 4,
+    };
+    let header = block.get(..size)?;
+    let flags = header[1];
+    let mut position = 2;
+    // Compressed and uncompressed sizes
+    for present in [0x40, 0x80] {
+        if flags & present != 0 {
+            read_vli(header, &mut position)?;
+        }
+    }
+    for _ in 0..=(flags & 0x03) {
+        let id = read_vli(header, &mut position)?;
+        let properties_size = read_vli(header, &mut position)? as usize;
+        let properties = header.get(position..position.checked_add(properties_size)?)?;
+        position += properties_size;
+        if id == LZMA2_FILTER_ID {
+            return match *properties.first()? {
+                40 => Some(u32::MAX as u64),
+                bits @ 0..40 => Some((2 | (bits as u64 & 1)) << (bits / 2 + 11)),
+                _ => None,
+            };
+        }
+    }
+    None
+}
+
+/// Limits for extract, downloads and extractions are throttled separately
+/// so network keeps going while decompressor is busy (and vice versa)
+pub struct Scheduler {
+    downloads: Semaphore,
+    extractions: Semaphore,
+    memory: Semaphore,
+    memory_budget_mib: u32,
+    queue_size: usize,
+}
+
+impl Scheduler {
+    /// Every limit is at least 1, memory budget is in MiB
+    pub fn new(downloads: usize, extractions: usize, memory_budget_mib: u32) -> Self {
+        let downloads = downloads.max(1);
+        let extractions = extractions.max(1);
+        let memory_budget_mib = memory_budget_mib.max(1);
+        Self {
+            downloads: Semaphore::new(downloads),
+            extractions: Semaphore::new(extractions),
+            memory: Semaphore::new(memory_budget_mib as usize),
+            memory_budget_mib,
+            queue_size: downloads + extractions,
+        }
+    }
+
+    pub(crate) async fn download_slot(&self) -> SemaphorePermit<'_> {
+        self.downloads.acquire().await.unwrap()
+    }
+
+    /// MiB single extraction reserves for its dictionary, see [`dictionary_size`]
+    fn extraction_cost(&self, dictionary_size: Option<u64>) -> u32 {
+        let dictionary = dictionary_size.unwrap_or(DEFAULT_DICTIONARY_SIZE).div_ceil(1 << 20);
+        let cost = u32::try_from(dictionary).unwrap_or(u32::MAX).saturating_add(EXTRACTION_OVERHEAD_MIB);
+        // Budget smaller than single extraction still lets one run at a time
+        cost.min(self.memory_budget_mib)
+    }
+
+    /// Waits for both free extractor and enough memory budget for decoder with such dictionary
+    pub(crate) async fn extraction_slot(&self, dictionary_size: Option<u64>) -> (SemaphorePermit<'_>, SemaphorePermit<'_>) {
+        let extraction = self.extractions.acquire().await.unwrap();
+        let memory = self.memory.acquire_many(self.extraction_cost(dictionary_size)).await.unwrap();
+        (extraction, memory)
+    }
+
+    /// How many versions are worth being in progress at once
+    #[inline]
+    pub fn queue_size(&self) -> usize {
+        self.queue_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::io::{Cursor, Write};
+    use xz2::write::XzEncoder;
+
+    fn archive(preset: u32) -> Vec<u8> {
+        let mut encoder = XzEncoder::new(vec![], preset);
+        encoder.write_all(b"libil2cpp").unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn dictionary_size_of_presets() {
+        for (preset, size) in [(0, 256 << 10), (6, 8 << 20), (9, 64 << 20)] {
+            let archive = archive(preset);
+            let mut reader = Cursor::new(&archive);
+            let start = read_stream_start(&mut reader);
+            assert_eq!(dictionary_size(&start), Some(size), "-{}", preset);
+            // Nothing is lost for decoder once start is chained back in front of the rest
+            let mut rest = vec![];
+            reader.read_to_end(&mut rest).unwrap();
+            assert_eq!([start, rest].concat(), archive);
+        }
+        assert_eq!(dictionary_size(b"not an xz archive"), None);
+        assert_eq!(dictionary_size(&archive(6)[..14]), None);
+    }
+
+    #[test]
+    fn budget_blocks_extraction_going_over_it() {
+        let scheduler = Scheduler::new(1, 4, 100);
+        let first = scheduler.extraction_slot(Some(64 << 20)).now_or_never().unwrap();
+        assert!(scheduler.extraction_slot(Some(64 << 20)).now_or_never().is_none());
+        // Smaller dictionary still fits into what is left
+        let second = scheduler.extraction_slot(Some(8 << 20)).now_or_never().unwrap();
+        drop(first);
+        assert!(scheduler.extraction_slot(Some(64 << 20)).now_or_never().is_some());
+        drop(second);
+    }
+
+    #[test]
+    fn single_extraction_runs_within_small_budget() {
+        let scheduler = Scheduler::new(1, 4, 16);
+        let first = scheduler.extraction_slot(Some(64 << 20)).now_or_never().unwrap();
+        assert!(scheduler.extraction_slot(None).now_or_never().is_none());
+        drop(first);
+        assert!(scheduler.extraction_slot(None).now_or_never().is_some());
+    }
+}
@@ -0,0 +1,295 @@
+use globset::{GlobBuilder, GlobMatcher};
+use std::{cmp::Ordering, collections::BTreeMap, str::FromStr};
+use unity_version::{UnityVersion, UnityVersionType};
+
+use crate::error::{Error, Result};
+use crate::layout::Layout;
+use crate::table::read_metadata_version;
+use crate::version_parser::{is_version_string, DownloadableUnity};
+
+/// Either end of range, `2022.1` covers every `2022.1.x` release
+#[derive(Debug)]
+pub enum Bound {
+    Full(UnityVersion),
+    /// Major, minor and build, whichever were given
+    Partial(Vec<u16>),
+}
+
+impl Bound {
+    /// Where version lies relative to bound
+    fn compare(&self, unity_version: &UnityVersion) -> Ordering {
+        match self {
+            Bound::Full(bound) => unity_version.cmp(bound),
+            Bound::Partial(bound) => {
+                let parts = [unity_version.major, unity_version.minor, unity_version.build];
+                parts[..bound.len()].cmp(bound)
+            }
+        }
+    }
+}
+
+impl FromStr for Bound {
+    type Err = Error;
+
+    fn from_str(bound: &str) -> Result<Self> {
+        if is_version_string(bound) {
+            return Ok(Bound::Full(UnityVersion::from(bound)));
+        }
+        let parts = bound.split('.').map(|e| e.parse::<u16>().ok()).collect::<Option<Vec<_>>>();
+        match parts {
+            Some(parts) if (1..=3).contains(&parts.len()) => Ok(Bound::Partial(parts)),
+            _ => Err(Error::parse(format!("{} is neither version nor its prefix", bound))),
+        }
+    }
+}
+
+/// Single `--version` argument
+#[derive(Debug)]
+pub enum VersionPattern {
+    Exact(String),
+    /// Both ends are inclusive, missing one is unbounded
+    Range {
+        from: Option<Bound>,
+        to: Option<Bound>,
+    },
+    Glob(GlobMatcher),
+}
+
+impl VersionPattern {
+    pub fn matches(&self, unity_version: &DownloadableUnity) -> bool {
+        match self {
+            VersionPattern::Exact(version) => unity_version.name == *version || unity_version.inner.version() == *version,
+            VersionPattern::Range { from, to } => {
+                from.as_ref().map_or(true, |e| e.compare(&unity_version.inner).is_ge()) && to.as_ref().map_or(true, |e| e.compare(&unity_version.inner).is_le())
+            }
+            VersionPattern::Glob(glob) => glob.is_match(&unity_version.name),
+        }
+    }
+}
+
+impl FromStr for VersionPattern {
+    type Err = Error;
+
+    /// `2021.3.5f1`, `2021.3.0f1..2022.1`, `2022.2..` or `2022.3.*`
+    fn from_str(pattern: &str) -> Result<Self> {
+        let parse = |e: &str| match e {
+            "" => Ok(None),
+            e => e.parse::<Bound>().map(Some),
+        };
+        if let Some((from, to)) = pattern.split_once("..") {
+            return Ok(VersionPattern::Range {
+                from: parse(from)?,
+                to: parse(to)?,
+            });
+        }
+        if pattern.contains(['*', '?', '[', '{']) {
+            let glob = GlobBuilder::new(pattern)
+                .build()
+                .map_err(|e| Error::parse(format!("Bad version glob {}: {}", pattern, e)))?;
+            return Ok(VersionPattern::Glob(glob.compile_matcher()));
+        }
+        if !is_version_string(pattern) {
+            return Err(Error::parse(format!("{} is not a unity version", pattern)));
+        }
+        Ok(VersionPattern::Exact(pattern.to_string()))
+    }
+}
+
+/// Narrows down versions every command works on, empty selection keeps all of them
+#[derive(Debug, Default)]
+pub struct VersionSelection {
+    /// Version has to match any of them
+    pub patterns: Vec<VersionPattern>,
+    /// `LTS`, `TECH`, `BETA` or `ALPHA`, case insensitive
+    pub streams: Vec<String>,
+    /// Only versions already extracted have known metadata version
+    pub metadata_versions: Vec<u8>,
+    /// Applied after everything else, keeps newest release of every minor in each channel
+    pub latest_patch: bool,
+}
+
+/// Versions discovered from disk have no stream, alpha and beta can still be told by their type
+fn stream_of(unity_version: &DownloadableUnity) -> Option<String> {
+    match (&unity_version.stream, &unity_version.inner.r#type) {
+        (Some(stream), _) => Some(stream.to_uppercase()),
+        (None, UnityVersionType::Alpha) => Some("ALPHA".into()),
+        (None, UnityVersionType::Beta) => Some("BETA".into()),
+        (None, _) => None,
+    }
+}
+
+impl VersionSelection {
+    /// Nothing was asked for, every version is selected
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.streams.is_empty() && self.metadata_versions.is_empty() && !self.latest_patch
+    }
+
+    /// Fails instead of dropping version whose stream or metadata version a filter needs but isn't known
+    pub fn matches(&self, layout: &Layout, unity_version: &DownloadableUnity) -> Result<bool> {
+        if !self.patterns.is_empty() && !self.patterns.iter().any(|e| e.matches(unity_version)) {
+            return Ok(false);
+        }
+        if !self.streams.is_empty() {
+            let stream = stream_of(unity_version).ok_or_else(|| Error::parse(format!("Stream of {} is unknown, run fetch first", unity_version.name)))?;
+            if !self.streams.iter().any(|e| e.eq_ignore_ascii_case(&stream)) {
+                return Ok(false);
+            }
+        }
+        if !self.metadata_versions.is_empty() {
+            let directory = layout.version_directory(unity_version);
+            // Not extracted yet, so it has no metadata version to match
+            if !directory.exists() {
+                return Ok(false);
+            }
+            let metadata_version = read_metadata_version(&directory).map_err(|e| e.with_version(&unity_version.name))?;
+            if !self.metadata_versions.contains(&metadata_version) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Keeps order of `unity_versions`, which [`crate::version_parser::parse_unity_versions`] sorts
+    pub fn apply(&self, layout: &Layout, unity_versions: Vec<DownloadableUnity>) -> Result<Vec<DownloadableUnity>> {
+        let mut selected = vec![];
+        for unity_version in unity_versions {
+            if self.matches(layout, &unity_version)? {
+                selected.push(unity_version);
+            }
+        }
+        if !self.latest_patch {
+            return Ok(selected);
+        }
+
+        let mut latest = BTreeMap::new();
+        for unity_version in selected {
+            let key = (unity_version.inner.major, unity_version.inner.minor, unity_version.channel());
+            match latest.get(&key) {
+                Some(newest) if *newest >= unity_version => {}
+                _ => {
+                    latest.insert(key, unity_version);
+                }
+            }
+        }
+        let mut selected = latest.into_values().collect::<Vec<_>>();
+        selected.sort();
+        Ok(selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Release;
+    use std::{fs, process};
+
+    fn unity_version(version: &str, stream: Option<&str>) -> DownloadableUnity {
+        DownloadableUnity::try_from(Release {
+            version: version.into(),
+            changeset: "0".into(),
+            release_date: None,
+            stream: stream.map(str::to_string),
+            entitlements: vec![],
+        })
+        .unwrap()
+    }
+
+    fn matching(pattern: &str, versions: &[&str]) -> Vec<String> {
+        let pattern = pattern.parse::<VersionPattern>().unwrap();
+        versions
+            .iter()
+            .map(|e| unity_version(e, None))
+            .filter(|e| pattern.matches(e))
+            .map(|e| e.name)
+            .collect()
+    }
+
+    const VERSIONS: [&str; 6] = ["2020.3.48f1", "2021.1.0f1", "2021.3.5f1", "2021.3.30f1", "2022.1.0b2", "2022.2.1f1"];
+
+    #[test]
+    fn patterns_are_parsed() {
+        assert!(matches!("2021.3.5f1".parse(), Ok(VersionPattern::Exact(e)) if e == "2021.3.5f1"));
+        assert!(matches!(
+            "2021.3.0f1..2022.1".parse(),
+            Ok(VersionPattern::Range {
+                from: Some(Bound::Full(_)),
+                to: Some(Bound::Partial(_))
+            })
+        ));
+        assert!(matches!("2022.2..".parse(), Ok(VersionPattern::Range { from: Some(_), to: None })));
+        assert!(matches!("2022.3.*".parse(), Ok(VersionPattern::Glob(_))));
+        for bad in ["2021", "2021.3.x..", "latest", "2021.[..", "1.2.3.4.."] {
+            assert!(bad.parse::<VersionPattern>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn exact_and_glob() {
+        assert_eq!(matching("2021.3.5f1", &VERSIONS), ["2021.3.5f1"]);
+        assert_eq!(matching("2021.3.*", &VERSIONS), ["2021.3.5f1", "2021.3.30f1"]);
+        assert_eq!(matching("2022.?.*", &VERSIONS), ["2022.1.0b2", "2022.2.1f1"]);
+    }
+
+    #[test]
+    fn range_bounds() {
+        assert_eq!(matching("2021.3.5f1..2022.1", &VERSIONS), ["2021.3.5f1", "2021.3.30f1", "2022.1.0b2"]);
+        assert_eq!(matching("..2021", &VERSIONS), ["2020.3.48f1", "2021.1.0f1", "2021.3.5f1", "2021.3.30f1"]);
+        assert_eq!(matching("2022..", &VERSIONS), ["2022.1.0b2", "2022.2.1f1"]);
+        assert_eq!(matching("2021.3.6..", &VERSIONS), ["2021.3.30f1", "2022.1.0b2", "2022.2.1f1"]);
+        assert_eq!(matching("..", &VERSIONS), VERSIONS);
+        assert!(matching("2022.2..2021", &VERSIONS).is_empty());
+    }
+
+    #[test]
+    fn stream_filter() {
+        let selection = VersionSelection {
+            streams: vec!["lts".into(), "BETA".into()],
+            ..Default::default()
+        };
+        let layout = Layout::default();
+        assert!(selection.matches(&layout, &unity_version("2021.3.5f1", Some("LTS"))).unwrap());
+        assert!(!selection.matches(&layout, &unity_version("2022.2.1f1", Some("TECH"))).unwrap());
+        // Discovered from disk, stream is told by version type
+        assert!(selection.matches(&layout, &unity_version("2022.1.0b2", None)).unwrap());
+        assert!(!selection.matches(&layout, &unity_version("2022.1.0a2", None)).unwrap());
+        assert!(selection.matches(&layout, &unity_version("2021.3.5f1", None)).is_err());
+    }
+
+    #[test]
+    fn metadata_version_filter() {
+        let root = std::env::temp_dir().join(format!("libil2cpp-parser-selection-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let layout = Layout::new(&root, ".");
+        let extracted = unity_version("2021.3.5f1", None);
+        let vm = layout.version_directory(&extracted).join("vm");
+        fs::create_dir_all(&vm).unwrap();
+        fs::write(vm.join("MetadataCache.cpp"), "IL2CPP_ASSERT(version == 29);").unwrap();
+
+        let selection = |metadata_versions: Vec<u8>| VersionSelection {
+            metadata_versions,
+            ..Default::default()
+        };
+        assert!(selection(vec![27, 29]).matches(&layout, &extracted).unwrap());
+        assert!(!selection(vec![31]).matches(&layout, &extracted).unwrap());
+        assert!(!selection(vec![29]).matches(&layout, &unity_version("2021.3.6f1", None)).unwrap());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn latest_patch_per_minor_and_channel() {
+        let selection = VersionSelection {
+            latest_patch: true,
+            ..Default::default()
+        };
+        let versions = ["2021.3.5f1", "2021.3.30f1", "2021.3.10f1c1", "2021.3.2f1c1", "2021.2.19f1", "2022.3.0f1"]
+            .iter()
+            .map(|e| unity_version(e, None))
+            .collect();
+        let selected = selection.apply(&Layout::default(), versions).unwrap();
+        let names = selected.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names.len(), 4);
+        for name in ["2021.2.19f1", "2021.3.30f1", "2021.3.10f1c1", "2022.3.0f1"] {
+            assert!(names.contains(&name), "{:?}", names);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Deserialize;
+use std::{fs, path::Path};
+use unity_version::UnityVersion;
+
+use crate::error::{Error, Result};
+use crate::version_parser::is_version_string;
+
+/// Shipped list, config `skip-list` replaces it
+const BUILTIN_SKIP_LIST: &'static str = include_str!("../data/skip.toml");
+
+#[derive(Deserialize)]
+struct SkipRule {
+    from: Option<String>,
+    to: Option<String>,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+struct SkipFile {
+    revision: u32,
+    #[serde(default)]
+    skip: Vec<SkipRule>,
+}
+
+struct SkipRange {
+    from: Option<UnityVersion>,
+    to: Option<UnityVersion>,
+    reason: String,
+}
+
+/// Versions known to be useless for extraction, each with explanation why
+pub struct SkipList {
+    pub revision: u32,
+    ranges: Vec<SkipRange>,
+}
+
+fn parse_bound(bound: Option<String>) -> Result<Option<UnityVersion>, String> {
+    match bound {
+        Some(version) if is_version_string(&version) => Ok(Some(UnityVersion::from(version.as_str()))),
+        Some(version) => Err(format!("{} is not a unity version", version)),
+        None => Ok(None),
+    }
+}
+
+impl SkipList {
+    fn parse(content: &str) -> Result<Self, String> {
+        let file: SkipFile = toml::from_str(content).map_err(|e| e.to_string())?;
+        let mut ranges = Vec::with_capacity(file.skip.len());
+        for rule in file.skip {
+            ranges.push(SkipRange {
+                from: parse_bound(rule.from)?,
+                to: parse_bound(rule.to)?,
+                reason: rule.reason,
+            });
+        }
+        Ok(Self {
+            revision: file.revision,
+            ranges,
+        })
+    }
+
+    /// Built-in list unless `path` is given
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => {
+                let content = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+                Self::parse(&content).map_err(|e| Error::parse(e).with_path(path))
+            }
+            None => Self::parse(BUILTIN_SKIP_LIST).map_err(|e| Error::parse(format!("Built-in skip list is broken: {}", e))),
+        }
+    }
+
+    /// Why version is skipped, `None` when it isn't
+    pub fn reason(&self, unity_version: &UnityVersion) -> Option<&str> {
+        self.ranges
+            .iter()
+            .find(|e| e.from.as_ref().map_or(true, |from| unity_version >= from) && e.to.as_ref().map_or(true, |to| unity_version <= to))
+            .map(|e| e.reason.as_str())
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::error::{Error, Result};
+use crate::manifest::{FileDigest, TreeContents};
+use crate::version_parser::DownloadableUnity;
+use crate::writer::{copy_hashed, WriteError};
+
+static TEMPORARY_COUNTER: AtomicU64 = AtomicU64::new(0);
+/// Objects are shared by every tree, editing one through any of them would change all versions
+const OBJECT_MODE: u32 = 0o444;
+
+pub struct StoreStats {
+    pub versions: usize,
+    pub objects: usize,
+    /// What extracted trees would take without deduplication
+    pub logical_size: u64,
+    pub stored_size: u64,
+}
+
+/// Keeps every extracted file once under `objects/<2 hex>/<sha256>`, version trees are hardlinks into it
+/// and `manifests/<major>/<version>.json` tells which object lives at which path
+#[derive(Clone)]
+pub struct ContentStore {
+    root: PathBuf,
+}
+
+impl ContentStore {
+    /// Creates store directories under `root` unless they exist
+    pub fn open(root: &Path) -> Result<Self> {
+        for directory in ["objects", "tmp", "manifests"] {
+            let path = root.join(directory);
+            fs::create_dir_all(&path).map_err(|e| Error::io(&path, e))?;
+        }
+        Ok(Self { root: root.to_path_buf() })
+    }
+
+    fn object_path(&self, sha256: &str) -> PathBuf {
+        self.root.join("objects").join(&sha256[..2]).join(sha256)
+    }
+
+    fn manifest_path(&self, unity_version: &DownloadableUnity) -> PathBuf {
+        self.root
+            .join("manifests")
+            .join(unity_version.inner.major.to_string())
+            .join(format!("{}.json", unity_version.name))
+    }
+
+    /// Streams `reader` into store and places it at `output`, same content is stored only once
+    ///
+    /// Existing object is rehashed first and replaced when it no longer matches its name
+    pub(crate) fn put(&self, reader: &mut impl Read, output: &Path) -> Result<FileDigest, WriteError> {
+        let temporary = self
+            .root
+            .join("tmp")
+            .join(format!("{}-{}", process::id(), TEMPORARY_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let stored = match copy_hashed(reader, &temporary) {
+            Ok(stored) => stored,
+            Err(e) => {
+                let _ = fs::remove_file(&temporary);
+                return Err(e);
+            }
+        };
+        let object = self.object_path(&stored.sha256);
+        if FileDigest::of_file(&object).is_ok_and(|e| e == stored) {
+            let _ = fs::remove_file(&temporary);
+        } else {
+            fs::set_permissions(&temporary, fs::Permissions::from_mode(OBJECT_MODE)).map_err(|e| WriteError::io(&temporary, e))?;
+            let parent = object.parent().unwrap();
+            fs::create_dir_all(parent).map_err(|e| WriteError::io(parent, e))?;
+            fs::rename(&temporary, &object).map_err(|e| WriteError::io(&object, e))?;
+        }
+
+        self.link(&stored, output)?;
+        Ok(stored)
+    }
+
+    /// Hardlinks object to `output`, falls back to copy across filesystems or on link count limit
+    pub(crate) fn link(&self, stored: &FileDigest, output: &Path) -> Result<(), WriteError> {
+        let object = self.object_path(&stored.sha256);
+        let _ = fs::remove_file(output);
+        if fs::hard_link(&object, output).is_err() {
+            fs::copy(&object, output).map_err(|e| WriteError::io(output, e))?;
+        }
+        Ok(())
+    }
+
+    /// Describes what is wrong with object behind `stored`, `None` when it is intact
+    pub(crate) fn verify_object(&self, stored: &FileDigest) -> Option<String> {
+        let object = self.object_path(&stored.sha256);
+        match FileDigest::of_file(&object) {
+            Ok(actual) if actual == *stored => None,
+            Ok(_) => Some(format!("{} doesn't match its name", object.display())),
+            Err(e) => Some(format!("{} is unreadable: {}", object.display(), e)),
+        }
+    }
+
+    /// Flattens trees into `<tree>/<path inside of tree>` keys
+    pub(crate) fn write_manifest(&self, unity_version: &DownloadableUnity, trees: &BTreeMap<String, TreeContents>) -> Result<(), WriteError> {
+        let files = trees
+            .iter()
+            .flat_map(|(tree, contents)| contents.files.iter().map(move |(path, digest)| (format!("{}/{}", tree, path), digest)))
+            .collect::<BTreeMap<_, _>>();
+        let path = self.manifest_path(unity_version);
+        let parent = path.parent().unwrap();
+        fs::create_dir_all(parent).map_err(|e| WriteError::io(parent, e))?;
+        let content = serde_json::to_string_pretty(&files).map_err(|e| WriteError::io(&path, e.into()))?;
+        fs::write(&path, content).map_err(|e| WriteError::io(&path, e))
+    }
+
+    /// Counts versions and objects, sizes are of files as stored and as trees would take them
+    pub fn stats(&self) -> Result<StoreStats> {
+        let mut stats = StoreStats {
+            versions: 0,
+            objects: 0,
+            logical_size: 0,
+            stored_size: 0,
+        };
+
+        let manifests = self.root.join("manifests");
+        for major in fs::read_dir(&manifests).map_err(|e| Error::io(&manifests, e))?.flatten() {
+            for manifest in fs::read_dir(major.path()).into_iter().flatten().flatten() {
+                let content = fs::read_to_string(manifest.path()).map_err(|e| Error::io(manifest.path(), e))?;
+                let files: BTreeMap<String, FileDigest> = serde_json::from_str(&content).map_err(|e| Error::parse(e).with_path(manifest.path()))?;
+                stats.versions += 1;
+                stats.logical_size += files.values().map(|e| e.size).sum::<u64>();
+            }
+        }
+
+        let objects = self.root.join("objects");
+        for prefix in fs::read_dir(&objects).map_err(|e| Error::io(&objects, e))?.flatten() {
+            for object in fs::read_dir(prefix.path()).into_iter().flatten().flatten() {
+                stats.objects += 1;
+                stats.stored_size += object.metadata().map(|e| e.len()).unwrap_or(0);
+            }
+        }
+
+        Ok(stats)
+    }
+}
@@ -0,0 +1,567 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::{self, Display},
+    fs,
+};
+
+use crate::error::{Error, Result};
+use crate::header::{Field, Header, Kind, TypeDefinition};
+use crate::layout::Layout;
+use crate::version_parser::DownloadableUnity;
+
+/// Single difference of type between two versions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    Added {
+        kind: Kind,
+        size: Option<u64>,
+    },
+    Removed {
+        kind: Kind,
+    },
+    /// Nothing else is compared once struct became union or typedef
+    KindChanged {
+        from: Kind,
+        to: Kind,
+    },
+    SizeChanged {
+        from: Option<u64>,
+        to: Option<u64>,
+    },
+    AlignmentChanged {
+        from: Option<u64>,
+        to: Option<u64>,
+    },
+    /// Typedef aliases something else
+    TargetChanged {
+        from: String,
+        to: String,
+    },
+    FieldAdded {
+        field: String,
+        #[serde(rename = "type")]
+        ty: String,
+        offset: Option<u64>,
+    },
+    FieldRemoved {
+        field: String,
+        #[serde(rename = "type")]
+        ty: String,
+        offset: Option<u64>,
+    },
+    FieldRetyped {
+        field: String,
+        from: String,
+        to: String,
+    },
+    /// Field is out of order relative to other fields both versions have, indexes are among those fields
+    FieldReordered {
+        field: String,
+        from: usize,
+        to: usize,
+    },
+    /// Type is spelled the same while what it names changed size, e.g. typedef now aliases wider integer
+    FieldResized {
+        field: String,
+        from: Option<u64>,
+        to: Option<u64>,
+    },
+    /// Bit offsets are set only for bit-fields
+    OffsetChanged {
+        field: String,
+        from: Option<u64>,
+        to: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        from_bit: Option<u8>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        to_bit: Option<u8>,
+    },
+    VariantAdded {
+        variant: String,
+        value: Option<i64>,
+    },
+    VariantRemoved {
+        variant: String,
+        value: Option<i64>,
+    },
+    VariantChanged {
+        variant: String,
+        from: Option<i64>,
+        to: Option<i64>,
+    },
+    /// Definitions differ in something none of the above describes, e.g. order of enumerators with unchanged values
+    LayoutChanged,
+}
+
+/// `0x1c`, `?` when unknown
+fn hex(value: Option<u64>) -> String {
+    value.map_or("?".to_string(), |e| format!("{:#x}", e))
+}
+
+fn position(offset: Option<u64>, bit: Option<u8>) -> String {
+    match bit {
+        Some(bit) => format!("{}:{}", hex(offset), bit),
+        None => hex(offset),
+    }
+}
+
+fn value(value: Option<i64>) -> String {
+    value.map_or("?".to_string(), |e| e.to_string())
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { kind, size } => write!(f, "{} added, size {}", kind, hex(*size)),
+            Change::Removed { kind } => write!(f, "{} removed", kind),
+            Change::KindChanged { from, to } => write!(f, "{} became {}", from, to),
+            Change::SizeChanged { from, to } => write!(f, "size {} → {}", hex(*from), hex(*to)),
+            Change::AlignmentChanged { from, to } => write!(f, "alignment {} → {}", hex(*from), hex(*to)),
+            Change::TargetChanged { from, to } => write!(f, "alias of `{}` → `{}`", from, to),
+            Change::FieldAdded { field, ty, offset } => write!(f, "field `{}` added: `{}` at {}", field, ty, hex(*offset)),
+            Change::FieldRemoved { field, ty, offset } => write!(f, "field `{}` removed: `{}` at {}", field, ty, hex(*offset)),
+            Change::FieldRetyped { field, from, to } => write!(f, "field `{}` retyped: `{}` → `{}`", field, from, to),
+            Change::FieldReordered { field, from, to } => write!(f, "field `{}` reordered: #{} → #{}", field, from, to),
+            Change::FieldResized { field, from, to } => write!(f, "field `{}` size {} → {}", field, hex(*from), hex(*to)),
+            Change::OffsetChanged {
+                field,
+                from,
+                to,
+                from_bit,
+                to_bit,
+            } => write!(f, "field `{}` offset {} → {}", field, position(*from, *from_bit), position(*to, *to_bit)),
+            Change::VariantAdded { variant, value: v } => write!(f, "variant `{}` = {} added", variant, value(*v)),
+            Change::VariantRemoved { variant, value: v } => write!(f, "variant `{}` = {} removed", variant, value(*v)),
+            Change::VariantChanged { variant, from, to } => write!(f, "variant `{}` value {} → {}", variant, value(*from), value(*to)),
+            Change::LayoutChanged => write!(f, "layout changed"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TypeChanges {
+    pub name: String,
+    pub changes: Vec<Change>,
+}
+
+/// Every changed type between two adjacent versions
+#[derive(Debug, Serialize)]
+pub struct VersionChanges {
+    pub from: String,
+    pub to: String,
+    pub types: Vec<TypeChanges>,
+}
+
+/// Names both sequences share in the same relative order, the rest of them were moved
+fn longest_common_subsequence<'a>(old: &[&'a str], new: &[&'a str]) -> HashSet<&'a str> {
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let mut common = HashSet::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            common.insert(old[i]);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    common
+}
+
+/// Names of `fields` present in `other` too, in order of `fields`
+fn common<'a>(fields: &'a [Field], other: &HashMap<String, usize>) -> Vec<&'a str> {
+    fields.iter().map(|e| e.name.as_str()).filter(|e| other.contains_key(*e)).collect()
+}
+
+/// Appends how fields changed, fields are matched by name
+pub fn compare_fields(old: &[Field], new: &[Field], changes: &mut Vec<Change>) {
+    // First one wins should header ever repeat field name
+    let index = |fields: &[Field]| {
+        let mut index = HashMap::with_capacity(fields.len());
+        for (i, field) in fields.iter().enumerate() {
+            index.entry(field.name.clone()).or_insert(i);
+        }
+        index
+    };
+    let (old_index, new_index) = (index(old), index(new));
+
+    for field in old.iter().filter(|e| !new_index.contains_key(&e.name)) {
+        changes.push(Change::FieldRemoved {
+            field: field.name.clone(),
+            ty: field.ty.clone(),
+            offset: field.offset,
+        });
+    }
+
+    let (old_common, new_common) = (common(old, &new_index), common(new, &old_index));
+    let in_order = longest_common_subsequence(&old_common, &new_common);
+
+    for field in new {
+        let Some(previous) = old_index.get(&field.name).map(|e| &old[*e]) else {
+            changes.push(Change::FieldAdded {
+                field: field.name.clone(),
+                ty: field.ty.clone(),
+                offset: field.offset,
+            });
+            continue;
+        };
+        if previous.ty != field.ty {
+            changes.push(Change::FieldRetyped {
+                field: field.name.clone(),
+                from: previous.ty.clone(),
+                to: field.ty.clone(),
+            });
+        } else if previous.size != field.size {
+            changes.push(Change::FieldResized {
+                field: field.name.clone(),
+                from: previous.size,
+                to: field.size,
+            });
+        }
+        if !in_order.contains(field.name.as_str()) {
+            let position = |common: &[&str]| common.iter().position(|e| *e == field.name).unwrap_or_default();
+            changes.push(Change::FieldReordered {
+                field: field.name.clone(),
+                from: position(&old_common),
+                to: position(&new_common),
+            });
+        }
+        if (previous.offset, previous.bit_offset) != (field.offset, field.bit_offset) {
+            changes.push(Change::OffsetChanged {
+                field: field.name.clone(),
+                from: previous.offset,
+                to: field.offset,
+                from_bit: previous.bit_offset,
+                to_bit: field.bit_offset,
+            });
+        }
+    }
+}
+
+/// What changed in type between two versions, `None` when it doesn't exist in one of them
+pub fn compare_type(old: Option<&TypeDefinition>, new: Option<&TypeDefinition>) -> Vec<Change> {
+    let (old, new) = match (old, new) {
+        (None, None) => return vec![],
+        (None, Some(new)) => {
+            return vec![Change::Added {
+                kind: new.kind,
+                size: new.size,
+            }]
+        }
+        (Some(old), None) => return vec![Change::Removed { kind: old.kind }],
+        (Some(old), Some(new)) => (old, new),
+    };
+    if old.kind != new.kind {
+        return vec![Change::KindChanged { from: old.kind, to: new.kind }];
+    }
+
+    let mut changes = vec![];
+    if old.size != new.size {
+        changes.push(Change::SizeChanged { from: old.size, to: new.size });
+    }
+    if old.alignment != new.alignment {
+        changes.push(Change::AlignmentChanged {
+            from: old.alignment,
+            to: new.alignment,
+        });
+    }
+    if let (Some(from), Some(to)) = (&old.target, &new.target) {
+        if from != to {
+            changes.push(Change::TargetChanged {
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+    }
+    compare_fields(&old.fields, &new.fields, &mut changes);
+
+    let values = |type_definition: &TypeDefinition| type_definition.variants.iter().map(|e| (e.name.clone(), e.value)).collect::<HashMap<_, _>>();
+    let (old_values, new_values) = (values(old), values(new));
+    for variant in old.variants.iter().filter(|e| !new_values.contains_key(&e.name)) {
+        changes.push(Change::VariantRemoved {
+            variant: variant.name.clone(),
+            value: variant.value,
+        });
+    }
+    for variant in &new.variants {
+        match old_values.get(&variant.name) {
+            None => changes.push(Change::VariantAdded {
+                variant: variant.name.clone(),
+                value: variant.value,
+            }),
+            Some(previous) if *previous != variant.value => changes.push(Change::VariantChanged {
+                variant: variant.name.clone(),
+                from: *previous,
+                to: variant.value,
+            }),
+            Some(_) => {}
+        }
+    }
+    if changes.is_empty() && old != new {
+        changes.push(Change::LayoutChanged);
+    }
+    changes
+}
+
+/// Changed types sorted by name
+pub fn compare(old: &Header, new: &Header) -> Vec<TypeChanges> {
+    let names = old.types.keys().chain(new.types.keys()).collect::<BTreeSet<_>>();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let changes = compare_type(old.types.get(name), new.types.get(name));
+            (!changes.is_empty()).then(|| TypeChanges { name: name.clone(), changes })
+        })
+        .collect()
+}
+
+pub fn create_markdown(changes: &[VersionChanges]) -> String {
+    let mut md = "This file was autogenerated by libil2cpp-parser.\n".to_string();
+    for version in changes {
+        md.push_str(&format!("\n## {} → {}\n\n", version.from, version.to));
+        if version.types.is_empty() {
+            md.push_str("No changes.\n");
+            continue;
+        }
+        for type_changes in &version.types {
+            md.push_str(&format!("### {}\n\n", type_changes.name));
+            type_changes.changes.iter().for_each(|e| md.push_str(&format!("- {}\n", e)));
+            md.push('\n');
+        }
+        md.pop();
+    }
+    md
+}
+
+/// Compares `struct.h` of every consecutive pair of versions having it into `struct-changes.md` and `struct-changes.json`
+pub fn diff_structs(layout: &Layout, unity_versions: &[DownloadableUnity]) -> Result<()> {
+    let markdown = layout.struct_changes("md");
+    let json = layout.struct_changes("json");
+    let _ = fs::remove_file(&markdown);
+    let _ = fs::remove_file(&json);
+
+    let mut unity_versions = unity_versions
+        .iter()
+        .filter(|e| layout.version_directory(e).join("struct.h").exists())
+        .collect::<Vec<_>>();
+    unity_versions.sort();
+    unity_versions.dedup();
+    if unity_versions.len() < 2 {
+        return Ok(());
+    }
+
+    let mut changes = vec![];
+    let mut previous: Option<(&DownloadableUnity, Header)> = None;
+    for unity_version in unity_versions {
+        let header = Header::read(&layout.version_directory(unity_version).join("struct.h")).map_err(|e| e.with_version(&unity_version.inner))?;
+        if let Some((previous_version, previous_header)) = &previous {
+            changes.push(VersionChanges {
+                from: previous_version.name.clone(),
+                to: unity_version.name.clone(),
+                types: compare(previous_header, &header),
+            });
+        }
+        previous = Some((unity_version, header));
+    }
+
+    fs::write(&markdown, create_markdown(&changes)).map_err(|e| Error::io(&markdown, e))?;
+    let serialized = serde_json::to_string_pretty(&changes).map_err(|e| Error::parse(e).with_path(&json))?;
+    fs::write(&json, serialized).map_err(|e| Error::io(&json, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(old: &str, new: &str) -> Vec<Change> {
+        let (old, new) = (Header::parse(old), Header::parse(new));
+        compare_type(old.types.get("S"), new.types.get("S"))
+    }
+
+    fn offset_changed(field: &str, from: u64, to: u64) -> Change {
+        Change::OffsetChanged {
+            field: field.into(),
+            from: Some(from),
+            to: Some(to),
+            from_bit: None,
+            to_bit: None,
+        }
+    }
+
+    #[test]
+    fn field_added_shifts_offsets() {
+        let changes = changes("typedef struct S { int a; int b; } S;", "typedef struct S { int a; void* added; int b; } S;");
+        assert_eq!(
+            changes,
+            [
+                Change::SizeChanged { from: Some(8), to: Some(24) },
+                Change::AlignmentChanged { from: Some(4), to: Some(8) },
+                Change::FieldAdded {
+                    field: "added".into(),
+                    ty: "void*".into(),
+                    offset: Some(8),
+                },
+                offset_changed("b", 4, 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn field_removed() {
+        let changes = changes("typedef struct S { int a; short gone; short b; } S;", "typedef struct S { int a; short b; } S;");
+        assert_eq!(
+            changes,
+            [
+                Change::FieldRemoved {
+                    field: "gone".into(),
+                    ty: "short".into(),
+                    offset: Some(4),
+                },
+                offset_changed("b", 6, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn field_retyped() {
+        let changes = changes(
+            "typedef struct S { int32_t a; int32_t b; } S;",
+            "typedef struct S { uint32_t a; int64_t b; } S;",
+        );
+        assert_eq!(
+            changes,
+            [
+                Change::SizeChanged { from: Some(8), to: Some(16) },
+                Change::AlignmentChanged { from: Some(4), to: Some(8) },
+                Change::FieldRetyped {
+                    field: "a".into(),
+                    from: "int32_t".into(),
+                    to: "uint32_t".into(),
+                },
+                Change::FieldRetyped {
+                    field: "b".into(),
+                    from: "int32_t".into(),
+                    to: "int64_t".into(),
+                },
+                offset_changed("b", 4, 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn field_reordered() {
+        let changes = changes(
+            "typedef struct S { int a; int b; int c; int d; } S;",
+            "typedef struct S { int a; int c; int d; int b; } S;",
+        );
+        assert_eq!(
+            changes,
+            [
+                offset_changed("c", 8, 4),
+                offset_changed("d", 12, 8),
+                Change::FieldReordered {
+                    field: "b".into(),
+                    from: 1,
+                    to: 3,
+                },
+                offset_changed("b", 4, 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn bit_field_moved() {
+        let changes = changes(
+            "typedef struct S { uint8_t a : 1; uint8_t b : 1; } S;",
+            "typedef struct S { uint8_t a : 2; uint8_t b : 1; } S;",
+        );
+        assert_eq!(
+            changes,
+            [
+                Change::FieldRetyped {
+                    field: "a".into(),
+                    from: "uint8_t:1".into(),
+                    to: "uint8_t:2".into(),
+                },
+                Change::OffsetChanged {
+                    field: "b".into(),
+                    from: Some(0),
+                    to: Some(0),
+                    from_bit: Some(1),
+                    to_bit: Some(2),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn whitespace_and_declaration_order_are_ignored() {
+        let old = Header::parse("typedef int32_t Index;\ntypedef struct S { const char* name; Index i; } S;");
+        let new = Header::parse("typedef struct S\n{\n    const char *name;\n    Index   i;\n} S;\ntypedef int32_t Index;");
+        assert!(compare(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn types_and_variants() {
+        let old = Header::parse("typedef enum E { A, B, C } E; typedef struct Gone { int a; } Gone; typedef int32_t T;");
+        let new = Header::parse("typedef enum E { A, C, B, D } E; typedef union New { int a; } New; typedef int64_t T;");
+        let changes = compare(&old, &new).into_iter().map(|e| (e.name, e.changes)).collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                (
+                    "E".to_string(),
+                    vec![
+                        Change::VariantChanged {
+                            variant: "C".into(),
+                            from: Some(2),
+                            to: Some(1),
+                        },
+                        Change::VariantChanged {
+                            variant: "B".into(),
+                            from: Some(1),
+                            to: Some(2),
+                        },
+                        Change::VariantAdded {
+                            variant: "D".into(),
+                            value: Some(3),
+                        },
+                    ]
+                ),
+                ("Gone".to_string(), vec![Change::Removed { kind: Kind::Struct }]),
+                (
+                    "New".to_string(),
+                    vec![Change::Added {
+                        kind: Kind::Union,
+                        size: Some(4),
+                    }]
+                ),
+                (
+                    "T".to_string(),
+                    vec![
+                        Change::SizeChanged { from: Some(4), to: Some(8) },
+                        Change::AlignmentChanged { from: Some(4), to: Some(8) },
+                        Change::TargetChanged {
+                            from: "int32_t".into(),
+                            to: "int64_t".into(),
+                        },
+                    ]
+                ),
+            ]
+        );
+    }
+}
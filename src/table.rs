@@ -1,67 +1,127 @@
 use itertools::Itertools;
 use markdown_table::*;
-use std::path::PathBuf;
+use std::path::Path;
 use std::{collections::HashMap, fs::read_to_string};
 
-use crate::version_parser::DownloadableUnity;
+use crate::error::{Error, Result};
+use crate::layout::Layout;
+use crate::version_parser::{DownloadableUnity, ReleaseChannel};
 
 struct ProcessableUnity<'a> {
     inner: &'a DownloadableUnity,
     metadata_version: u8,
 }
 
-fn map_unity_version_to_metadata(unity: &Vec<DownloadableUnity>) -> Vec<ProcessableUnity> {
+/// Metadata version is taken from `version == N` assertion of metadata loader
+pub fn read_metadata_version(root: &Path) -> Result<u8> {
+    let vm = root.join("vm");
+    let mut path = vm.join("MetadataCache.cpp");
+    let mut content = read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+    let idx = match content.find("version == ") {
+        Some(idx) => idx,
+        None => {
+            path = vm.join("GlobalMetadata.cpp");
+            content = read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+            content
+                .find("version ==")
+                .ok_or_else(|| Error::parse("Failed to determine metadata version assertion index").with_path(&path))?
+        }
+    };
+    content
+        .get(idx + 11..idx + 13)
+        .and_then(|e| e.parse().ok())
+        .ok_or_else(|| Error::parse("Got non-u8 metadata version").with_path(&path))
+}
+
+fn map_unity_version_to_metadata<'a>(layout: &Layout, unity: &'a Vec<DownloadableUnity>) -> Result<Vec<ProcessableUnity<'a>>> {
     let dirs = unity.iter().filter(|e| e.is_downloaded).collect::<Vec<_>>();
 
     let mut map = Vec::with_capacity(dirs.len());
     for unity_version in dirs {
-        let as_pathbuf: PathBuf = <DownloadableUnity as Into<PathBuf>>::into(unity_version.clone());
-        if !as_pathbuf.exists() {
+        let directory = layout.version_directory(unity_version);
+        if !directory.exists() {
             continue;
         }
 
-        let vm = as_pathbuf.join("vm");
-        let mut content = read_to_string(vm.join("MetadataCache.cpp")).unwrap();
-
-        let idx = content.find("version == ").unwrap_or_else(|| {
-            content = read_to_string(vm.join("GlobalMetadata.cpp")).unwrap();
-            content.find("version ==").expect("Failed to determine metadata version assertion index")
-        });
         map.push(ProcessableUnity {
             inner: unity_version,
-            metadata_version: content[idx + 11..idx + 13].parse().expect("Got non-u8 metadata version"),
+            metadata_version: read_metadata_version(&directory).map_err(|e| e.with_version(&unity_version.inner))?,
         });
     }
 
-    map
+    Ok(map)
 }
 
-pub fn create_table(unity: &Vec<DownloadableUnity>) -> impl Iterator<Item = (u16, String)> + use<'_> {
-    let heading = ["Unity version", "Metadata version", "Changeset", "Single-header struct", "Single-header API"]
-        .map(|e| Heading::new(e.to_string(), Some(HeadingAlignment::Center)))
-        .to_vec();
+/// Markdown table per major, sorted by it, links are relative to `directory` table is written into
+pub fn create_table(layout: &Layout, directory: &Path, unity: &Vec<DownloadableUnity>) -> Result<Vec<(u16, String)>> {
+    let heading = [
+        "Unity version",
+        "Release date",
+        "Stream",
+        "Metadata version",
+        "Changeset",
+        "Single-header struct",
+        "Single-header API",
+    ]
+    .map(|e| Heading::new(e.to_string(), Some(HeadingAlignment::Center)))
+    .to_vec();
 
-    let mapping = map_unity_version_to_metadata(unity);
-    let majors = mapping.iter().map(|e| e.inner.inner.major);
-    let majors_nodup = majors.clone().dedup();
-    let mut groups = HashMap::with_capacity(8);
-    majors_nodup.for_each(|e| {
-        groups.insert(e, Vec::with_capacity(majors.clone().filter(|m| e == *m).count()));
-    });
-    mapping.into_iter().for_each(|unity| {
-        let major = unity.inner.inner.major;
-        let as_pathbuf: PathBuf = <DownloadableUnity as Into<PathBuf>>::into(unity.inner.clone());
-        groups.get_mut(&major).unwrap().push(vec![
-            unity.inner.inner.version(),
+    let mapping = map_unity_version_to_metadata(layout, unity)?;
+    let mut groups: HashMap<_, Vec<_>> = HashMap::with_capacity(8);
+    for unity in mapping {
+        let link = layout.link_from(directory, unity.inner);
+        let link = link
+            .to_str()
+            .ok_or_else(|| Error::parse("Non-UTF-8 path can't be linked from markdown").with_path(&link))?;
+        let stream = match (&unity.inner.stream, unity.inner.entitlements.is_empty()) {
+            (Some(stream), true) => stream.clone(),
+            (Some(stream), false) => format!("{} ({})", stream, unity.inner.entitlements.join(", ")),
+            (None, _) => "-".to_string(),
+        };
+        groups.entry(unity.inner.inner.major).or_default().push(vec![
+            unity.inner.name.clone(),
+            unity.inner.release_day().unwrap_or("-").to_string(),
+            stream,
             unity.metadata_version.to_string(),
             unity.inner.changeset.to_string(),
-            format!("Go to [struct.h]({})", as_pathbuf.join("struct.h").to_str().unwrap().to_string()),
-            format!("Go to [api.h]({})", as_pathbuf.join("api.h").to_str().unwrap().to_string()),
+            format!("Go to [struct.h]({}/struct.h)", link),
+            format!("Go to [api.h]({}/api.h)", link),
         ]);
-    });
-    groups.into_iter().sorted_by_key(|(major, _)| *major).map(move |(major, unitys)| {
-        let mut table = MarkdownTable::new(unitys);
-        table.with_headings(heading.clone());
-        (major, table.as_markdown().unwrap())
-    })
+    }
+    groups
+        .into_iter()
+        .sorted_by_key(|(major, _)| *major)
+        .map(move |(major, unitys)| {
+            let mut table = MarkdownTable::new(unitys);
+            table.with_headings(heading.clone());
+            let markdown = table.as_markdown().map_err(|_| Error::parse(format!("Failed to render table of {}", major)))?;
+            Ok((major, markdown))
+        })
+        .collect()
+}
+
+/// Whole VERSIONS.md, china and experimental releases go into their own sections after global ones
+pub fn create_markdown(layout: &Layout, directory: &Path, unity: &Vec<DownloadableUnity>) -> Result<String> {
+    let sections = [
+        (ReleaseChannel::Global, None),
+        (ReleaseChannel::China, Some("Unity China")),
+        (ReleaseChannel::Experimental, Some("Experimental")),
+    ];
+    let mut md = "This file was autogenerated by libil2cpp-parser.\n\n".to_string();
+    for (channel, title) in sections {
+        let channel_versions = unity.iter().filter(|e| e.channel() == channel).cloned().collect::<Vec<_>>();
+        let table = create_table(layout, directory, &channel_versions)?
+            .into_iter()
+            .map(|e| format!("<details><summary>Expand Unity {}</summary>\n\n{}</details>", e.0, e.1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if table.is_empty() {
+            continue;
+        }
+        if let Some(title) = title {
+            md.push_str(&format!("\n## {}\n\n", title));
+        }
+        md.push_str(&table);
+    }
+    Ok(md)
 }
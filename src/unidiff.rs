@@ -0,0 +1,718 @@
+use std::collections::HashMap;
+
+/// Lines of context around every hunk
+const CONTEXT: isize = 3;
+
+/// Whitespace as C `isspace` sees it in C locale
+#[inline]
+fn is_space(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\n' | 0x0b | 0x0c | b'\r')
+}
+
+/// Splits keeping `\n`, last line may lack it
+fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|e| *e == b'\n').collect()
+}
+
+/// `-B` with `-w` treats whitespace-only lines as blank
+#[inline]
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(|e| is_space(*e))
+}
+
+/// Single change as GNU diff scripts it, `deleted` lines of old file at `line0` replaced by `inserted` ones at `line1`
+struct Change {
+    line0: isize,
+    line1: isize,
+    deleted: isize,
+    inserted: isize,
+    /// Every deleted and inserted line is blank
+    ignore: bool,
+}
+
+/// Marks lines matching nothing or too much in the other file, so they don't steer the comparison
+fn discard_confusing_lines(equivs: [&[usize]; 2], classes: usize, changed: &mut [Vec<bool>; 2]) -> [(Vec<usize>, Vec<usize>); 2] {
+    let mut equiv_count = [vec![0usize; classes], vec![0usize; classes]];
+    for f in 0..2 {
+        for e in equivs[f] {
+            equiv_count[f][*e] += 1;
+        }
+    }
+
+    let mut discarded = [vec![0u8; equivs[0].len()], vec![0u8; equivs[1].len()]];
+    for f in 0..2 {
+        let end = equivs[f].len();
+        let counts = &equiv_count[1 - f];
+        let mut many = 5;
+        let mut tem = end / 64;
+        loop {
+            tem >>= 2;
+            if tem == 0 {
+                break;
+            }
+            many *= 2;
+        }
+        for i in 0..end {
+            let nmatch = counts[equivs[f][i]];
+            if nmatch == 0 {
+                discarded[f][i] = 1;
+            } else if nmatch > many {
+                discarded[f][i] = 2;
+            }
+        }
+    }
+
+    // Provisional discards stand only inside of runs of real ones
+    for discards in discarded.iter_mut() {
+        let end = discards.len();
+        let mut i = 0;
+        while i < end {
+            if discards[i] == 2 {
+                discards[i] = 0;
+            } else if discards[i] != 0 {
+                let mut provisional = 0;
+                let mut j = i;
+                while j < end && discards[j] != 0 {
+                    if discards[j] == 2 {
+                        provisional += 1;
+                    }
+                    j += 1;
+                }
+                while j > i && discards[j - 1] == 2 {
+                    j -= 1;
+                    discards[j] = 0;
+                    provisional -= 1;
+                }
+                let length = j - i;
+
+                if provisional * 4 > length {
+                    while j > i {
+                        j -= 1;
+                        if discards[j] == 2 {
+                            discards[j] = 0;
+                        }
+                    }
+                } else {
+                    let mut minimum = 1;
+                    let mut tem = length >> 2;
+                    loop {
+                        tem >>= 2;
+                        if tem == 0 {
+                            break;
+                        }
+                        minimum <<= 1;
+                    }
+                    minimum += 1;
+
+                    let mut consec = 0;
+                    let mut j = 0;
+                    while j < length {
+                        if discards[i + j] != 2 {
+                            consec = 0;
+                        } else {
+                            consec += 1;
+                            if minimum == consec {
+                                j -= consec;
+                            } else if minimum < consec {
+                                discards[i + j] = 0;
+                            }
+                        }
+                        j += 1;
+                    }
+
+                    let mut consec = 0;
+                    for j in 0..length {
+                        if j >= 8 && discards[i + j] == 1 {
+                            break;
+                        }
+                        if discards[i + j] == 2 {
+                            consec = 0;
+                            discards[i + j] = 0;
+                        } else if discards[i + j] == 0 {
+                            consec = 0;
+                        } else {
+                            consec += 1;
+                        }
+                        if consec == 3 {
+                            break;
+                        }
+                    }
+
+                    i += length - 1;
+
+                    let mut consec = 0;
+                    for j in 0..length {
+                        if j >= 8 && discards[i - j] == 1 {
+                            break;
+                        }
+                        if discards[i - j] == 2 {
+                            consec = 0;
+                            discards[i - j] = 0;
+                        } else if discards[i - j] == 0 {
+                            consec = 0;
+                        } else {
+                            consec += 1;
+                        }
+                        if consec == 3 {
+                            break;
+                        }
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+
+    let mut undiscarded = [(vec![], vec![]), (vec![], vec![])];
+    for f in 0..2 {
+        for (i, discard) in discarded[f].iter().enumerate() {
+            if *discard == 0 {
+                undiscarded[f].0.push(equivs[f][i]);
+                undiscarded[f].1.push(i);
+            } else {
+                changed[f][i] = true;
+            }
+        }
+    }
+    undiscarded
+}
+
+/// Myers' comparison over undiscarded lines, with the cost cutoff of GNU diff
+struct Comparison<'a> {
+    xvec: &'a [usize],
+    yvec: &'a [usize],
+    xindexes: &'a [usize],
+    yindexes: &'a [usize],
+    fdiag: Vec<isize>,
+    bdiag: Vec<isize>,
+    offset: isize,
+    too_expensive: isize,
+}
+
+struct Partition {
+    xmid: isize,
+    ymid: isize,
+    lo_minimal: bool,
+    hi_minimal: bool,
+}
+
+impl<'a> Comparison<'a> {
+    #[inline]
+    fn equal(&self, x: isize, y: isize) -> bool {
+        self.xvec[x as usize] == self.yvec[y as usize]
+    }
+
+    #[inline]
+    fn fd(&mut self, d: isize) -> &mut isize {
+        &mut self.fdiag[(d + self.offset) as usize]
+    }
+
+    #[inline]
+    fn bd(&mut self, d: isize) -> &mut isize {
+        &mut self.bdiag[(d + self.offset) as usize]
+    }
+
+    /// Finds midpoint of the shortest edit script, or a good enough one once it gets too expensive
+    fn diag(&mut self, xoff: isize, xlim: isize, yoff: isize, ylim: isize, find_minimal: bool) -> Partition {
+        let dmin = xoff - ylim;
+        let dmax = xlim - yoff;
+        let fmid = xoff - yoff;
+        let bmid = xlim - ylim;
+        let (mut fmin, mut fmax, mut bmin, mut bmax) = (fmid, fmid, bmid, bmid);
+        let odd = (fmid - bmid) & 1 != 0;
+
+        *self.fd(fmid) = xoff;
+        *self.bd(bmid) = xlim;
+
+        let mut c = 1;
+        loop {
+            if fmin > dmin {
+                fmin -= 1;
+                *self.fd(fmin - 1) = -1;
+            } else {
+                fmin += 1;
+            }
+            if fmax < dmax {
+                fmax += 1;
+                *self.fd(fmax + 1) = -1;
+            } else {
+                fmax -= 1;
+            }
+            let mut d = fmax;
+            while d >= fmin {
+                let tlo = *self.fd(d - 1);
+                let thi = *self.fd(d + 1);
+                let x0 = if tlo < thi { thi } else { tlo + 1 };
+                let (mut x, mut y) = (x0, x0 - d);
+                while x < xlim && y < ylim && self.equal(x, y) {
+                    x += 1;
+                    y += 1;
+                }
+                *self.fd(d) = x;
+                if odd && bmin <= d && d <= bmax && *self.bd(d) <= x {
+                    return Partition {
+                        xmid: x,
+                        ymid: y,
+                        lo_minimal: true,
+                        hi_minimal: true,
+                    };
+                }
+                d -= 2;
+            }
+
+            if bmin > dmin {
+                bmin -= 1;
+                *self.bd(bmin - 1) = isize::MAX;
+            } else {
+                bmin += 1;
+            }
+            if bmax < dmax {
+                bmax += 1;
+                *self.bd(bmax + 1) = isize::MAX;
+            } else {
+                bmax -= 1;
+            }
+            let mut d = bmax;
+            while d >= bmin {
+                let tlo = *self.bd(d - 1);
+                let thi = *self.bd(d + 1);
+                let x0 = if tlo < thi { tlo } else { thi - 1 };
+                let (mut x, mut y) = (x0, x0 - d);
+                while xoff < x && yoff < y && self.equal(x - 1, y - 1) {
+                    x -= 1;
+                    y -= 1;
+                }
+                *self.bd(d) = x;
+                if !odd && fmin <= d && d <= fmax && x <= *self.fd(d) {
+                    return Partition {
+                        xmid: x,
+                        ymid: y,
+                        lo_minimal: true,
+                        hi_minimal: true,
+                    };
+                }
+                d -= 2;
+            }
+
+            if !find_minimal && c >= self.too_expensive {
+                let (mut fxybest, mut fxbest) = (-1, 0);
+                let mut d = fmax;
+                while d >= fmin {
+                    let mut x = (*self.fd(d)).min(xlim);
+                    let mut y = x - d;
+                    if ylim < y {
+                        x = ylim + d;
+                        y = ylim;
+                    }
+                    if fxybest < x + y {
+                        fxybest = x + y;
+                        fxbest = x;
+                    }
+                    d -= 2;
+                }
+
+                let (mut bxybest, mut bxbest) = (isize::MAX, 0);
+                let mut d = bmax;
+                while d >= bmin {
+                    let mut x = xoff.max(*self.bd(d));
+                    let mut y = x - d;
+                    if y < yoff {
+                        x = yoff + d;
+                        y = yoff;
+                    }
+                    if x + y < bxybest {
+                        bxybest = x + y;
+                        bxbest = x;
+                    }
+                    d -= 2;
+                }
+
+                return if (xlim + ylim) - bxybest < fxybest - (xoff + yoff) {
+                    Partition {
+                        xmid: fxbest,
+                        ymid: fxybest - fxbest,
+                        lo_minimal: true,
+                        hi_minimal: false,
+                    }
+                } else {
+                    Partition {
+                        xmid: bxbest,
+                        ymid: bxybest - bxbest,
+                        lo_minimal: false,
+                        hi_minimal: true,
+                    }
+                };
+            }
+            c += 1;
+        }
+    }
+
+    fn compare(&mut self, mut xoff: isize, mut xlim: isize, mut yoff: isize, mut ylim: isize, find_minimal: bool, changed: &mut [Vec<bool>; 2]) {
+        while xoff < xlim && yoff < ylim && self.equal(xoff, yoff) {
+            xoff += 1;
+            yoff += 1;
+        }
+        while xoff < xlim && yoff < ylim && self.equal(xlim - 1, ylim - 1) {
+            xlim -= 1;
+            ylim -= 1;
+        }
+
+        if xoff == xlim {
+            (yoff..ylim).for_each(|y| changed[1][self.yindexes[y as usize]] = true);
+        } else if yoff == ylim {
+            (xoff..xlim).for_each(|x| changed[0][self.xindexes[x as usize]] = true);
+        } else {
+            let part = self.diag(xoff, xlim, yoff, ylim, find_minimal);
+            self.compare(xoff, part.xmid, yoff, part.ymid, part.lo_minimal, changed);
+            self.compare(part.xmid, xlim, part.ymid, ylim, part.hi_minimal, changed);
+        }
+    }
+}
+
+/// Slides every run of changes to the latest position where it still means the same, merging neighbouring runs
+fn shift_boundaries(equivs: [&[usize]; 2], changed: &mut [Vec<bool>; 2]) {
+    for f in 0..2 {
+        // Sentinels at both ends spare bounds checks, index `k` lives at `k + 1`
+        let padded = |e: &Vec<bool>| {
+            let mut padded = Vec::with_capacity(e.len() + 2);
+            padded.push(false);
+            padded.extend_from_slice(e);
+            padded.push(false);
+            padded
+        };
+        let mut this = padded(&changed[f]);
+        let other = padded(&changed[1 - f]);
+        let equivs = equivs[f];
+        let i_end = equivs.len() as isize;
+        let ch = |e: &Vec<bool>, k: isize| e[(k + 1) as usize];
+        let (mut i, mut j): (isize, isize) = (0, 0);
+
+        loop {
+            while i < i_end && !ch(&this, i) {
+                while ch(&other, j) {
+                    j += 1;
+                }
+                j += 1;
+                i += 1;
+            }
+            if i == i_end {
+                break;
+            }
+
+            let mut start = i;
+            i += 1;
+            while ch(&this, i) {
+                i += 1;
+            }
+            while ch(&other, j) {
+                j += 1;
+            }
+
+            let mut corresponding;
+            loop {
+                let runlength = i - start;
+
+                while start > 0 && equivs[(start - 1) as usize] == equivs[(i - 1) as usize] {
+                    start -= 1;
+                    this[(start + 1) as usize] = true;
+                    i -= 1;
+                    this[(i + 1) as usize] = false;
+                    while ch(&this, start - 1) {
+                        start -= 1;
+                    }
+                    j -= 1;
+                    while ch(&other, j) {
+                        j -= 1;
+                    }
+                }
+
+                corresponding = if ch(&other, j - 1) { i } else { i_end };
+
+                while i != i_end && equivs[start as usize] == equivs[i as usize] {
+                    this[(start + 1) as usize] = false;
+                    start += 1;
+                    this[(i + 1) as usize] = true;
+                    i += 1;
+                    while ch(&this, i) {
+                        i += 1;
+                    }
+                    j += 1;
+                    while ch(&other, j) {
+                        j += 1;
+                        corresponding = i;
+                    }
+                }
+
+                if runlength == i - start {
+                    break;
+                }
+            }
+
+            while corresponding < i {
+                start -= 1;
+                this[(start + 1) as usize] = true;
+                i -= 1;
+                this[(i + 1) as usize] = false;
+                j -= 1;
+                while ch(&other, j) {
+                    j -= 1;
+                }
+            }
+        }
+
+        changed[f] = this[1..this.len() - 1].to_vec();
+    }
+}
+
+/// Turns per-line flags into changes, `offset` is number of identical lines skipped at start
+fn build_script(changed: &[Vec<bool>; 2], offset: isize, lines: [&[&[u8]]; 2]) -> Vec<Change> {
+    let ch = |f: usize, k: isize| k >= 0 && changed[f][k as usize];
+    let mut script = vec![];
+    let (mut i0, mut i1) = (changed[0].len() as isize, changed[1].len() as isize);
+    while i0 >= 0 || i1 >= 0 {
+        if ch(0, i0 - 1) || ch(1, i1 - 1) {
+            let (line0, line1) = (i0, i1);
+            while ch(0, i0 - 1) {
+                i0 -= 1;
+            }
+            while ch(1, i1 - 1) {
+                i1 -= 1;
+            }
+            let (line0, line1, deleted, inserted) = (i0 + offset, i1 + offset, line0 - i0, line1 - i1);
+            let ignore = lines[0][line0 as usize..(line0 + deleted) as usize].iter().all(|e| is_blank(e))
+                && lines[1][line1 as usize..(line1 + inserted) as usize].iter().all(|e| is_blank(e));
+            script.push(Change {
+                line0,
+                line1,
+                deleted,
+                inserted,
+                ignore,
+            });
+        }
+        i0 -= 1;
+        i1 -= 1;
+    }
+    script.reverse();
+    script
+}
+
+fn push_range(out: &mut Vec<u8>, a: isize, b: isize) {
+    let (a, b) = (a + 1, b + 1);
+    let range = match b.cmp(&a) {
+        std::cmp::Ordering::Less => format!("{},0", b),
+        std::cmp::Ordering::Equal => format!("{}", b),
+        std::cmp::Ordering::Greater => format!("{},{}", a, b - a + 1),
+    };
+    out.extend_from_slice(range.as_bytes());
+}
+
+fn push_line(out: &mut Vec<u8>, mark: u8, line: &[u8]) {
+    out.push(mark);
+    out.extend_from_slice(line);
+    if line.last() != Some(&b'\n') {
+        out.extend_from_slice(b"\n\\ No newline at end of file\n");
+    }
+}
+
+/// Appends what `diff -purwB --label <old_label> --label <new_label>` prints for these files, nothing if they don't differ
+pub fn write(old: &[u8], new: &[u8], old_label: &str, new_label: &str, out: &mut Vec<u8>) {
+    if old == new {
+        return;
+    }
+    let lines = [split_lines(old), split_lines(new)];
+
+    // Identical ends are skipped except for context worth of lines, changes may still shift into those
+    let prefix = lines[0].iter().zip(&lines[1]).take_while(|(a, b)| a == b).count();
+    let prefix = prefix.saturating_sub(CONTEXT as usize);
+    let suffix = lines[0][prefix..]
+        .iter()
+        .rev()
+        .zip(lines[1][prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = suffix.saturating_sub(CONTEXT as usize);
+
+    // Lines are equal when they are equal without any whitespace
+    let mut classes = HashMap::new();
+    let equivs = [0, 1].map(|f| {
+        lines[f][prefix..lines[f].len() - suffix]
+            .iter()
+            .map(|e| {
+                let key = e.iter().copied().filter(|c| !is_space(*c)).collect::<Vec<_>>();
+                let next = classes.len();
+                *classes.entry(key).or_insert(next)
+            })
+            .collect::<Vec<_>>()
+    });
+    let equivs = [equivs[0].as_slice(), equivs[1].as_slice()];
+
+    let mut changed = [vec![false; equivs[0].len()], vec![false; equivs[1].len()]];
+    let undiscarded = discard_confusing_lines(equivs, classes.len(), &mut changed);
+
+    let diags = undiscarded[0].0.len() + undiscarded[1].0.len() + 3;
+    let mut too_expensive = 1;
+    let mut remaining = diags;
+    while remaining != 0 {
+        too_expensive <<= 1;
+        remaining >>= 2;
+    }
+    let mut comparison = Comparison {
+        xvec: &undiscarded[0].0,
+        yvec: &undiscarded[1].0,
+        xindexes: &undiscarded[0].1,
+        yindexes: &undiscarded[1].1,
+        fdiag: vec![0; diags],
+        bdiag: vec![0; diags],
+        offset: undiscarded[1].0.len() as isize + 1,
+        too_expensive: too_expensive.max(4096),
+    };
+    comparison.compare(0, undiscarded[0].0.len() as isize, 0, undiscarded[1].0.len() as isize, false, &mut changed);
+    shift_boundaries(equivs, &mut changed);
+
+    let script = build_script(&changed, prefix as isize, [&lines[0], &lines[1]]);
+    let (n0, n1) = (lines[0].len() as isize, lines[1].len() as isize);
+    let mut header_written = false;
+    // Function line search never goes above where previous one stopped
+    let mut last_search = 0;
+    let mut last_match: Option<isize> = None;
+
+    let mut start = 0;
+    while start < script.len() {
+        // Changes closer than both contexts together share a hunk, blank ones only need to touch it
+        let mut end = start;
+        loop {
+            let top0 = script[end].line0 + script[end].deleted;
+            let Some(next) = script.get(end + 1) else {
+                break;
+            };
+            let threshold = if next.ignore { CONTEXT } else { 2 * CONTEXT + 1 };
+            if next.line0 - top0 >= threshold {
+                break;
+            }
+            end += 1;
+        }
+        let hunk = &script[start..=end];
+        start = end + 1;
+
+        if hunk.iter().all(|e| e.ignore) {
+            continue;
+        }
+
+        let last = &hunk[hunk.len() - 1];
+        let first0 = (hunk[0].line0 - CONTEXT).max(0);
+        let first1 = (hunk[0].line1 - CONTEXT).max(0);
+        let last0 = last.line0 + last.deleted - 1;
+        let last1 = last.line1 + last.inserted - 1;
+        let last0 = if last0 < n0 - CONTEXT { last0 + CONTEXT } else { n0 - 1 };
+        let last1 = if last1 < n1 - CONTEXT { last1 + CONTEXT } else { n1 - 1 };
+
+        let mut function = None;
+        let mut i = first0;
+        let previous_search = last_search;
+        last_search = first0;
+        while previous_search < i {
+            i -= 1;
+            let line = lines[0][i as usize];
+            if line.first().is_some_and(|c| c.is_ascii_alphabetic() || *c == b'$' || *c == b'_') {
+                last_match = Some(i);
+                function = Some(line);
+                break;
+            }
+        }
+        let function = function.or(last_match.map(|e| lines[0][e as usize]));
+
+        if !header_written {
+            out.extend_from_slice(format!("--- {}\n+++ {}\n", old_label, new_label).as_bytes());
+            header_written = true;
+        }
+        out.extend_from_slice(b"@@ -");
+        push_range(out, first0, last0);
+        out.extend_from_slice(b" +");
+        push_range(out, first1, last1);
+        out.extend_from_slice(b" @@");
+        if let Some(function) = function {
+            let function = function.strip_suffix(b"\n").unwrap_or(function);
+            let begin = function.iter().position(|c| !is_space(*c)).unwrap_or(function.len());
+            let mut end = (begin + 40).min(function.len());
+            while begin < end && is_space(function[end - 1]) {
+                end -= 1;
+            }
+            out.push(b' ');
+            out.extend_from_slice(&function[begin..end]);
+        }
+        out.push(b'\n');
+
+        let (mut i, mut j) = (first0, first1);
+        let mut changes = hunk.iter().peekable();
+        while i <= last0 || j <= last1 {
+            match changes.peek() {
+                Some(change) if i >= change.line0 => {
+                    for _ in 0..change.deleted {
+                        push_line(out, b'-', lines[0][i as usize]);
+                        i += 1;
+                    }
+                    for _ in 0..change.inserted {
+                        push_line(out, b'+', lines[1][j as usize]);
+                        j += 1;
+                    }
+                    changes.next();
+                }
+                _ => {
+                    push_line(out, b' ', lines[0][i as usize]);
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    /// `expected.diff` of every fixture is output of `diff -purwB --label=old.h --label=new.h old.h new.h`
+    fn check(case: &str) {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/unidiff").join(case);
+        let read = |name| fs::read(directory.join(name)).unwrap();
+        let mut out = vec![];
+        write(&read("old.h"), &read("new.h"), "old.h", "new.h", &mut out);
+        assert_eq!(String::from_utf8_lossy(&out), String::from_utf8_lossy(&read("expected.diff")));
+    }
+
+    #[test]
+    fn merges_hunks_within_context() {
+        check("merged_hunks");
+    }
+
+    #[test]
+    fn suppresses_blank_line_hunks() {
+        check("blank_only");
+        check("blank_lines");
+    }
+
+    #[test]
+    fn ignores_whitespace() {
+        check("whitespace");
+    }
+
+    #[test]
+    fn function_names() {
+        check("function_names");
+    }
+
+    #[test]
+    fn missing_newline_at_end() {
+        check("no_newline_old");
+        check("no_newline_new");
+        check("no_newline_both");
+    }
+
+    #[test]
+    fn empty_and_identical_inputs() {
+        check("empty_old");
+        check("empty_new");
+        check("identical");
+    }
+}
@@ -1,13 +1,48 @@
+use colour::*;
 use std::cmp::Ordering;
 use std::fmt::{self, Display};
-use std::path::PathBuf;
-use unity_version::UnityVersion;
+use unity_version::{UnityVersion, UnityVersionType};
+
+use crate::catalog::Release;
+use crate::error::{Error, Result};
+
+/// Where release comes from, each one gets its own VERSIONS.md section
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum ReleaseChannel {
+    Global,
+    /// `c` releases such as `2022.3.10f1c1`, served from unity china CDN
+    China,
+    /// `x` releases
+    Experimental,
+}
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Clone)]
 pub struct DownloadableUnity {
     pub inner: UnityVersion,
+    /// Version exactly as unity spells it, installer URLs need it verbatim (`2022.3.10f1c1`)
+    pub name: String,
     pub changeset: String,
     pub is_downloaded: bool,
+    /// ISO 8601 timestamp as reported by unity
+    pub release_date: Option<String>,
+    /// `LTS`, `TECH`, `BETA` or `ALPHA`
+    pub stream: Option<String>,
+    pub entitlements: Vec<String>,
+}
+
+impl DownloadableUnity {
+    /// Date part of [`Self::release_date`]
+    pub fn release_day(&self) -> Option<&str> {
+        self.release_date.as_deref().and_then(|e| e.split('T').next())
+    }
+
+    pub fn channel(&self) -> ReleaseChannel {
+        match self.inner.r#type {
+            UnityVersionType::China => ReleaseChannel::China,
+            UnityVersionType::Experimental => ReleaseChannel::Experimental,
+            _ => ReleaseChannel::Global,
+        }
+    }
 }
 
 impl Display for DownloadableUnity {
@@ -22,24 +57,63 @@ impl Ord for DownloadableUnity {
     }
 }
 
-impl From<(String, String)> for DownloadableUnity {
-    fn from((inner, changeset): (String, String)) -> Self {
-        DownloadableUnity {
-            inner: UnityVersion::from(inner.as_str()),
-            changeset,
-            is_downloaded: false,
+impl TryFrom<Release> for DownloadableUnity {
+    type Error = Error;
+
+    fn try_from(release: Release) -> Result<Self> {
+        if !is_version_string(&release.version) {
+            return Err(Error::parse(format!("{} is not a unity version", release.version)).with_version(&release.version));
         }
+        Ok(DownloadableUnity {
+            inner: UnityVersion::from(release.version.as_str()),
+            name: release.version,
+            changeset: release.changeset,
+            is_downloaded: false,
+            release_date: release.release_date,
+            stream: release.stream,
+            entitlements: release.entitlements,
+        })
     }
 }
 
-impl Into<PathBuf> for DownloadableUnity {
-    fn into(self) -> PathBuf {
-        PathBuf::from(format!("{}/{}", self.inner.major, self.inner.version()))
+/// Checks `2021.3.5f1`-like strings before handing them to [`UnityVersion::from`]
+pub fn is_version_string(version: &str) -> bool {
+    let mut parts = version.split('.');
+    let (Some(major), Some(minor), Some(rest), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    let is_number = |e: &str| !e.is_empty() && e.bytes().all(|c| c.is_ascii_digit());
+    let Some(type_index) = rest.find(|c: char| c.is_ascii_alphabetic()) else {
+        return false;
+    };
+    let (build, suffix) = rest.split_at(type_index);
+
+    // Suffix is `f1` or `f1c1` alike: type letters each followed by a number
+    let mut suffix = suffix;
+    while !suffix.is_empty() {
+        let letter = suffix.as_bytes()[0];
+        let number_end = suffix[1..].find(|c: char| !c.is_ascii_digit()).map_or(suffix.len(), |e| e + 1);
+        if !b"abcfpx".contains(&letter) || !is_number(&suffix[1..number_end]) {
+            return false;
+        }
+        suffix = &suffix[number_end..];
     }
+
+    is_number(major) && is_number(minor) && is_number(build)
 }
 
-pub fn parse_unity_versions(pairs: Vec<(String, String)>) -> Vec<DownloadableUnity> {
-    let mut unity_versions = pairs.into_iter().map(|e| DownloadableUnity::from(e)).collect::<Vec<_>>();
+/// Releases that don't parse are reported and skipped so one odd entry doesn't hide the rest
+pub fn parse_unity_versions(releases: Vec<Release>) -> Vec<DownloadableUnity> {
+    let mut unity_versions = releases
+        .into_iter()
+        .filter_map(|e| match DownloadableUnity::try_from(e) {
+            Ok(unity_version) => Some(unity_version),
+            Err(e) => {
+                yellow_ln!("Skipping release: {}", e);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
     unity_versions.sort();
     unity_versions.dedup();
 
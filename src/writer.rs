@@ -1,75 +1,509 @@
+use colour::*;
+use sha2::{Digest, Sha256};
 use std::{
-    borrow::Cow,
-    fs,
-    io::{ErrorKind, Read},
-    path::Path,
+    collections::{BTreeMap, VecDeque},
+    ffi::OsStr,
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Write},
+    os::unix,
+    path::{Component, Path, PathBuf},
 };
-use tar::Archive;
+use tar::{Archive, EntryType};
 use xz2::read::XzDecoder;
 
-use unity_version::UnityVersion;
+use crate::filter::ExtractionFilter;
+use crate::manifest::{FileDigest, TreeContents};
+use crate::store::ContentStore;
+use crate::version_parser::DownloadableUnity;
 
-pub struct LibIl2CppWriter<'a> {
-    unity_version: &'a UnityVersion,
-    buffer: Vec<u8>,
+/// Same limit as linux `ELOOP`
+const MAX_LINK_HOPS: usize = 40;
+
+#[derive(Debug)]
+pub enum WriteError {
+    /// Archive is not xz/tar or it was cut off
+    Archive(io::Error),
+    /// Single entry header or content couldn't be read
+    BadEntry(io::Error),
+    NonUtf8Path(PathBuf),
+    /// Entry name is absolute or has `..` in it
+    PathTraversal(PathBuf),
+    /// Symlink or hardlink which points outside of extracted tree
+    LinkEscape {
+        path: PathBuf,
+        target: PathBuf,
+    },
+    PermissionDenied(PathBuf),
+    DiskFull(PathBuf),
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
 }
 
-impl<'a> LibIl2CppWriter<'a> {
-    pub fn new(unity_version: &'a UnityVersion, buffer: Vec<u8>) -> Self {
-        Self { unity_version, buffer }
+impl WriteError {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        match source.kind() {
+            ErrorKind::PermissionDenied => WriteError::PermissionDenied(path.to_path_buf()),
+            ErrorKind::StorageFull => WriteError::DiskFull(path.to_path_buf()),
+            _ => WriteError::Io {
+                path: path.to_path_buf(),
+                source,
+            },
+        }
     }
+}
 
-    #[inline]
-    fn output_directory(&self) -> String {
-        format!("{}/{}", self.unity_version.major, self.unity_version.version())
+impl Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::Archive(e) => write!(f, "broken archive: {}", e),
+            WriteError::BadEntry(e) => write!(f, "broken archive entry: {}", e),
+            WriteError::NonUtf8Path(path) => write!(f, "non-utf8 entry name {}", path.display()),
+            WriteError::PathTraversal(path) => write!(f, "entry {} points outside of archive root", path.display()),
+            WriteError::LinkEscape { path, target } => write!(f, "link {} points outside of its tree to {}", path.display(), target.display()),
+            WriteError::PermissionDenied(path) => write!(f, "permission denied for {}", path.display()),
+            WriteError::DiskFull(path) => write!(f, "disk is full while writing {}", path.display()),
+            WriteError::Io { path, source } => write!(f, "failed to write {}: {}", path.display(), source),
+        }
+    }
+}
+
+/// Rejects absolute and `..` paths, returns normalized components otherwise
+fn normal_components(path: &Path) -> Result<Vec<&OsStr>, WriteError> {
+    let mut components = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return Err(WriteError::PathTraversal(path.to_path_buf())),
+        }
+    }
+    Ok(components)
+}
+
+/// Symlink target is relative to link itself, it must not climb above tree root
+pub(crate) fn is_inside_tree(link: &Path, target: &Path) -> bool {
+    let mut depth = link.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+/// Follows `link` through other links of the same tree, `false` once it climbs above tree root, turns absolute or loops
+///
+/// Each link may stay inside of tree on its own while chain of them does not, e.g. `sub/up -> ..` and `out -> sub/up/..`
+pub(crate) fn resolves_inside_tree(symlinks: &BTreeMap<String, String>, link: &str) -> bool {
+    let mut directory = link.split('/').map(str::to_string).collect::<Vec<_>>();
+    let mut components = VecDeque::new();
+    let mut hops = 0;
+    // Link itself is the first one expanded
+    let mut next = directory.pop().and_then(|_| symlinks.get(link));
+    loop {
+        if let Some(target) = next.take() {
+            hops += 1;
+            if target.starts_with('/') || hops > MAX_LINK_HOPS {
+                return false;
+            }
+            target.split('/').rev().for_each(|e| components.push_front(e.to_string()));
+        }
+        let Some(component) = components.pop_front() else {
+            return true;
+        };
+        match component.as_str() {
+            "" | "." => {}
+            ".." => {
+                if directory.pop().is_none() {
+                    return false;
+                }
+            }
+            _ => {
+                directory.push(component);
+                next = symlinks.get(&directory.join("/"));
+                if next.is_some() {
+                    directory.pop();
+                }
+            }
+        }
+    }
+}
+
+/// Unlike [`io::copy`] tells apart broken archive from failing disk, and hashes content on the way
+pub fn copy_hashed(reader: &mut impl Read, output: &Path) -> Result<FileDigest, WriteError> {
+    let mut file = File::create(output).map_err(|e| WriteError::io(output, e))?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(WriteError::Archive(e)),
+        };
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+        file.write_all(&buffer[..read]).map_err(|e| WriteError::io(output, e))?;
+    }
+    Ok(FileDigest {
+        size,
+        sha256: format!("{:x}", hasher.finalize()),
+    })
+}
+
+/// Archive path with `/` separators, which is what [`ExtractionFilter`] globs are matched against
+fn join_components(components: &Vec<&OsStr>) -> String {
+    components.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+pub struct LibIl2CppWriter<'a, R: Read> {
+    unity_version: &'a DownloadableUnity,
+    filter: &'a ExtractionFilter,
+    store: Option<&'a ContentStore>,
+    reader: R,
+}
+
+impl<'a, R: Read> LibIl2CppWriter<'a, R> {
+    /// `reader` must yield raw `.tar.xz` editor archive, it is consumed as entries are decompressed
+    pub fn new(unity_version: &'a DownloadableUnity, filter: &'a ExtractionFilter, reader: R) -> Self {
+        Self {
+            unity_version,
+            filter,
+            store: None,
+            reader,
+        }
+    }
+
+    /// Files go through deduplicated store and trees are built from links to it
+    pub fn with_store(mut self, store: Option<&'a ContentStore>) -> Self {
+        self.store = store;
+        self
     }
 
     #[inline]
-    fn format_output_directory(&self, raw_path: &Cow<'_, Path>) -> String {
-        format!(
-            "{}/{}",
-            self.output_directory(),
-            raw_path.as_os_str().to_str().unwrap().replace("Editor/Data/il2cpp/libil2cpp/", "")
-        )
+    pub fn output_directories(&self) -> Vec<PathBuf> {
+        self.filter.output_directories(self.unity_version)
     }
 
-    fn ensure_version(&self) -> Result<(), &str> {
-        fs::create_dir_all(self.output_directory()).or(Err("Failed to create directory"))
+    fn ensure_version(&self) -> Result<(), WriteError> {
+        for output_directory in self.output_directories() {
+            fs::create_dir_all(&output_directory).map_err(|e| WriteError::io(&output_directory, e))?;
+        }
+        Ok(())
     }
 
-    pub fn write(&self) -> Result<(), String> {
-        let decoder = XzDecoder::new(self.buffer.as_slice());
-        let mut tar = Archive::new(decoder);
+    fn ensure_parent(output: &Path) -> Result<(), WriteError> {
+        match output.parent() {
+            Some(parent) => fs::create_dir_all(parent).map_err(|e| WriteError::io(parent, e)),
+            None => Ok(()),
+        }
+    }
 
-        let _ = self.ensure_version().or(Err("Failed to create version directory"));
+    /// Earlier entries may have placed symlinks into tree, anything written through them would land outside of it
+    fn has_symlink_parent(tree: &Path, relative: &Path) -> bool {
+        let mut parent = tree.to_path_buf();
+        for component in relative.parent().into_iter().flat_map(Path::components) {
+            parent.push(component);
+            match fs::symlink_metadata(&parent) {
+                Ok(metadata) if metadata.file_type().is_symlink() => return true,
+                Ok(_) => {}
+                // Nothing exists deeper either, ensure_parent creates real directories there
+                Err(_) => break,
+            }
+        }
+        false
+    }
+
+    /// Previous partial run may have left something at this path
+    fn remove_existing(output: &Path) -> Result<(), WriteError> {
+        match fs::symlink_metadata(output) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(output).map_err(|e| WriteError::io(output, e)),
+            Ok(_) => fs::remove_file(output).map_err(|e| WriteError::io(output, e)),
+            Err(_) => Ok(()),
+        }
+    }
 
-        for entry in tar.entries().unwrap() {
-            let raw_entry = entry.unwrap();
-            let raw_path = raw_entry.path().unwrap();
+    /// Returns what was placed into every tree, keyed by tree name
+    pub fn write(self) -> Result<BTreeMap<String, TreeContents>, WriteError> {
+        self.ensure_version()?;
 
-            if !raw_path.to_str().unwrap().contains("libil2cpp") {
+        let output_directories = self.output_directories();
+        let filter = self.filter;
+        let store = self.store;
+        let mut contents = output_directories.iter().map(|_| TreeContents::default()).collect::<Vec<_>>();
+        let decoder = XzDecoder::new(self.reader);
+        let mut tar = Archive::new(decoder);
+
+        for entry in tar.entries().map_err(WriteError::Archive)? {
+            let mut raw_entry = entry.map_err(WriteError::BadEntry)?;
+            let raw_path = raw_entry.path().map_err(WriteError::BadEntry)?.into_owned();
+
+            let components = normal_components(&raw_path)?;
+            let routes = filter.route(&join_components(&components)).collect::<Vec<_>>();
+            if routes.is_empty() {
                 continue;
             }
-            let output = self.format_output_directory(&raw_path);
-            let size = raw_entry.size();
+            if components.iter().any(|e| e.to_str().is_none()) {
+                return Err(WriteError::NonUtf8Path(raw_path));
+            }
 
-            let mut buffer = vec![];
-            let _ = raw_entry.take(size).read_to_end(&mut buffer).unwrap();
+            // Entry content can be read only once, other trees get copy of the first one
+            let mut first: Option<(PathBuf, FileDigest)> = None;
+            for (tree, relative) in routes {
+                let output = output_directories[tree].join(&relative);
+                let key = relative.to_string_lossy().to_string();
+                if Self::has_symlink_parent(&output_directories[tree], &relative) {
+                    return Err(WriteError::PathTraversal(raw_path));
+                }
 
-            match fs::write(&output, buffer) {
-                Ok(_) => {}
-                Err(e) => {
-                    match e.kind() {
-                        ErrorKind::IsADirectory => {
-                            let _ = fs::create_dir_all(output).unwrap();
+                match raw_entry.header().entry_type() {
+                    EntryType::Directory => fs::create_dir_all(&output).map_err(|e| WriteError::io(&output, e))?,
+                    EntryType::Regular | EntryType::Continuous => {
+                        Self::ensure_parent(&output)?;
+                        Self::remove_existing(&output)?;
+                        let digest = match (store, &first) {
+                            (Some(store), Some((_, digest))) => {
+                                store.link(digest, &output)?;
+                                digest.clone()
+                            }
+                            (None, Some((first_output, digest))) => {
+                                fs::copy(first_output, &output).map_err(|e| WriteError::io(&output, e))?;
+                                digest.clone()
+                            }
+                            (Some(store), None) => store.put(&mut raw_entry, &output)?,
+                            (None, None) => copy_hashed(&mut raw_entry, &output)?,
+                        };
+                        first.get_or_insert_with(|| (output, digest.clone()));
+                        contents[tree].files.insert(key, digest);
+                    }
+                    EntryType::Symlink => {
+                        let target = raw_entry
+                            .link_name()
+                            .map_err(WriteError::BadEntry)?
+                            .ok_or_else(|| WriteError::BadEntry(ErrorKind::InvalidData.into()))?;
+                        if !is_inside_tree(&relative, &target) {
+                            return Err(WriteError::LinkEscape {
+                                path: raw_path,
+                                target: target.into_owned(),
+                            });
+                        }
+                        Self::ensure_parent(&output)?;
+                        Self::remove_existing(&output)?;
+                        unix::fs::symlink(&target, &output).map_err(|e| WriteError::io(&output, e))?;
+                        contents[tree].symlinks.insert(key, target.to_string_lossy().to_string());
+                    }
+                    EntryType::Link => {
+                        let target = raw_entry
+                            .link_name()
+                            .map_err(WriteError::BadEntry)?
+                            .ok_or_else(|| WriteError::BadEntry(ErrorKind::InvalidData.into()))?;
+                        // Hardlinks name their target by archive path of wherever tar met the inode first,
+                        // it may be taken by another tree or by none
+                        let target_path = join_components(&normal_components(&target)?);
+                        let source = filter
+                            .route(&target_path)
+                            .filter(|(other, relative)| {
+                                let path = output_directories[*other].join(relative);
+                                !Self::has_symlink_parent(&output_directories[*other], relative) && fs::symlink_metadata(&path).is_ok_and(|e| e.is_file())
+                            })
+                            .min_by_key(|(other, _)| *other != tree);
+                        let Some((source_tree, source_relative)) = source else {
+                            yellow_ln!("Skipping {} as its hardlink target {} wasn't extracted", raw_path.display(), target.display());
+                            continue;
+                        };
+                        let source = output_directories[source_tree].join(&source_relative);
+                        Self::ensure_parent(&output)?;
+                        Self::remove_existing(&output)?;
+                        if fs::hard_link(&source, &output).is_err() {
+                            fs::copy(&source, &output).map_err(|e| WriteError::io(&output, e))?;
                         }
-                        _ => unreachable!(),
+                        let digest = match contents[source_tree].files.get(source_relative.to_string_lossy().as_ref()) {
+                            Some(digest) => digest.clone(),
+                            None => FileDigest::of_file(&output).map_err(|e| WriteError::io(&output, e))?,
+                        };
+                        contents[tree].files.insert(key, digest);
                     }
-                    continue;
+                    // Devices, fifos and such have nothing to do with sources
+                    _ => {}
                 }
             }
         }
 
-        Ok(())
+        // Drain the rest of stream so xz checks its integrity and the downloader is not cut off
+        let mut decoder = tar.into_inner();
+        io::copy(&mut decoder, &mut io::sink()).map_err(WriteError::Archive)?;
+
+        for (tree, tree_contents) in contents.iter().enumerate() {
+            let escaping = tree_contents
+                .symlinks
+                .iter()
+                .find(|(link, _)| !resolves_inside_tree(&tree_contents.symlinks, link));
+            if let Some((link, target)) = escaping {
+                return Err(WriteError::LinkEscape {
+                    path: output_directories[tree].join(link),
+                    target: PathBuf::from(target),
+                });
+            }
+        }
+
+        Ok(contents
+            .into_iter()
+            .enumerate()
+            .map(|(tree, e)| (filter.tree_name(tree).to_string(), e))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Release;
+    use crate::config::TreeRule;
+    use crate::layout::Layout;
+    use std::process;
+    use tar::{Builder, Header};
+    use xz2::write::XzEncoder;
+
+    enum Entry<'a> {
+        Directory,
+        File(&'a str),
+        Symlink(&'a str),
+        Hardlink(&'a str),
+    }
+
+    /// Names are written raw, `tar` refuses to build headers with `..` or absolute paths on its own
+    fn archive(entries: &[(&str, Entry)]) -> Vec<u8> {
+        let mut builder = Builder::new(XzEncoder::new(vec![], 1));
+        for (name, entry) in entries {
+            let mut header = Header::new_gnu();
+            let gnu = header.as_gnu_mut().unwrap();
+            gnu.name[..name.len()].copy_from_slice(name.as_bytes());
+            let (entry_type, content, link) = match entry {
+                Entry::Directory => (EntryType::Directory, "", ""),
+                Entry::File(content) => (EntryType::Regular, *content, ""),
+                Entry::Symlink(target) => (EntryType::Symlink, "", *target),
+                Entry::Hardlink(target) => (EntryType::Link, "", *target),
+            };
+            gnu.linkname[..link.len()].copy_from_slice(link.as_bytes());
+            header.set_entry_type(entry_type);
+            header.set_mode(0o644);
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Extracts into `<temp>/<case>/2021/2021.3.5f1`, directory above the tree is where escapes would land
+    fn extract(case: &str, entries: &[(&str, Entry)]) -> (PathBuf, Result<BTreeMap<String, TreeContents>, WriteError>) {
+        let root = std::env::temp_dir().join(format!("libil2cpp-parser-writer-{}-{}", process::id(), case));
+        let _ = fs::remove_dir_all(&root);
+        let rules = vec![TreeRule {
+            name: "libil2cpp".into(),
+            root: PathBuf::from("."),
+            strip_prefix: "lib".into(),
+            include: vec!["lib/**".into()],
+            exclude: vec![],
+        }];
+        let filter = ExtractionFilter::new(&rules, Layout::new(&root, ".")).unwrap();
+        let unity_version = DownloadableUnity::try_from(Release {
+            version: "2021.3.5f1".into(),
+            changeset: "0".into(),
+            release_date: None,
+            stream: None,
+            entitlements: vec![],
+        })
+        .unwrap();
+        let result = LibIl2CppWriter::new(&unity_version, &filter, &archive(entries)[..]).write();
+        (root, result)
+    }
+
+    #[test]
+    fn parent_directory_is_refused() {
+        let (root, result) = extract("parent", &[("lib/../escape.h", Entry::File("x"))]);
+        assert!(matches!(result, Err(WriteError::PathTraversal(_))));
+        assert!(!root.join("2021/2021.3.5f1/escape.h").exists());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn absolute_path_is_refused() {
+        let (root, result) = extract("absolute", &[("/lib/absolute.h", Entry::File("x"))]);
+        assert!(matches!(result, Err(WriteError::PathTraversal(_))));
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn escaping_symlink_is_refused() {
+        let (root, result) = extract("symlink", &[("lib/vm/up.h", Entry::Symlink("../../outside.h"))]);
+        assert!(matches!(result, Err(WriteError::LinkEscape { .. })));
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn writing_through_chained_symlinks_is_refused() {
+        let (root, result) = extract(
+            "chained-write",
+            &[
+                ("lib/sub/", Entry::Directory),
+                ("lib/sub/up", Entry::Symlink("..")),
+                ("lib/out", Entry::Symlink("sub/up/..")),
+                ("lib/out/escape.h", Entry::File("x")),
+            ],
+        );
+        assert!(matches!(result, Err(WriteError::PathTraversal(_))));
+        assert!(!root.join("2021/escape.h").exists());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn chained_symlinks_leading_outside_are_refused() {
+        let (root, result) = extract(
+            "chained",
+            &[
+                ("lib/sub/", Entry::Directory),
+                ("lib/sub/up", Entry::Symlink("..")),
+                ("lib/out", Entry::Symlink("sub/up/..")),
+            ],
+        );
+        assert!(matches!(result, Err(WriteError::LinkEscape { .. })));
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn links_inside_of_tree_are_kept() {
+        let (root, result) = extract(
+            "inside",
+            &[
+                ("lib/vm/Class.h", Entry::File("class")),
+                ("lib/vm/same", Entry::Symlink(".")),
+                ("lib/link.h", Entry::Symlink("vm/same/Class.h")),
+                ("lib/hard.h", Entry::Hardlink("lib/vm/Class.h")),
+            ],
+        );
+        let contents = result.unwrap().remove("libil2cpp").unwrap();
+        assert_eq!(contents.files["hard.h"], contents.files["vm/Class.h"]);
+        assert_eq!(contents.symlinks["link.h"], "vm/same/Class.h");
+        assert_eq!(fs::read_to_string(root.join("2021/2021.3.5f1/link.h")).unwrap(), "class");
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn hardlink_to_file_outside_of_tree_is_skipped() {
+        let (root, result) = extract(
+            "hardlink",
+            &[("other/Class.h", Entry::File("class")), ("lib/hard.h", Entry::Hardlink("other/Class.h"))],
+        );
+        let contents = result.unwrap().remove("libil2cpp").unwrap();
+        assert!(contents.files.is_empty());
+        assert!(!root.join("2021/2021.3.5f1/hard.h").exists());
+        let _ = fs::remove_dir_all(root);
     }
 }
@@ -0,0 +1,32 @@
+int first(void)
+{
+    int local0 = 0;
+    int local1 = 1;
+    int local2 = 2;
+    int local3 = 3;
+    int local4 = 4;
+    int local5 = 5;
+    int local6 = 6;
+    int local7 = 7;
+    int local8 = 8;
+    int local9 = 9;
+    int local10 = 10;
+    int local11 = 11;
+}
+
+static struct Thing* second(int x)
+{
+    x += 0;
+    x += 1;
+    x += 2;
+    x += 3;
+    x += 4;
+    x += 5;
+    x += 6;
+    x += 7;
+    x += 8;
+    x += 9;
+    x += 10;
+    x += 11;
+    return 0;
+}
@@ -0,0 +1,40 @@
+int field0;
+int field1;
+int field2;
+int field3;
+int field4;
+int field5;
+int field6;
+int field7;
+int field8;
+int field9;
+int field10;
+int field11;
+int field12;
+int field13;
+int field14;
+int field15;
+int field16;
+int field17;
+int field18;
+int field19;
+int field20;
+int field21;
+int field22;
+int field23;
+int field24;
+int field25;
+int field26;
+int field27;
+int field28;
+int field29;
+int field30;
+int field31;
+int field32;
+int field33;
+int field34;
+int field35;
+int field36;
+int field37;
+int field38;
+int field39;
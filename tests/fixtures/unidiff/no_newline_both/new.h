@@ -0,0 +1,11 @@
+int field0;
+int field1;
+int field2;
+int field3;
+int field4;
+int field5;
+int field6;
+int field7;
+int field8;
+int field9;
+int other;
\ No newline at end of file
@@ -0,0 +1,20 @@
+typedef struct A
+{
+  int   a ;
+	char*	b;
+} A;
+typedef struct A
+{
+    int a;
+    char* b;
+} A;
+typedef struct A
+{
+    int a;
+    char* renamed;
+} A;
+typedef struct A
+{
+    int a;
+    char* b;
+} A;